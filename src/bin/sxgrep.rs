@@ -0,0 +1,139 @@
+use std::collections::HashMap;
+use std::process::ExitCode;
+use term_experiment::reader::read_all_with_lines;
+use term_experiment::rewrite::TreePattern;
+use term_experiment::sxfmt::{format_path, PrettyExpr, PrettyFormatter};
+
+const WIDTH: usize = 80;
+
+struct Match {
+    path: Vec<usize>,
+    line: usize,
+    expr: PrettyExpr,
+}
+
+enum Mode {
+    Print,
+    Count,
+    Paths,
+}
+
+fn main() -> ExitCode {
+    let mut mode = Mode::Print;
+    let mut args = vec![];
+    for arg in std::env::args().skip(1) {
+        match arg.as_str() {
+            "--count" => mode = Mode::Count,
+            "--paths" => mode = Mode::Paths,
+            _ => args.push(arg),
+        }
+    }
+
+    let (pattern, files) = match args.split_first() {
+        Some((pattern, files)) if !files.is_empty() => (pattern, files),
+        _ => {
+            eprintln!("usage: sxgrep [--count | --paths] PATTERN FILE...");
+            return ExitCode::from(2);
+        }
+    };
+    let pattern = match TreePattern::new(pattern) {
+        Ok(pattern) => pattern,
+        Err(e) => {
+            eprintln!("sxgrep: invalid pattern: {}", e);
+            return ExitCode::from(2);
+        }
+    };
+
+    let pf = PrettyFormatter::new(WIDTH, 2);
+    let mut found = false;
+    let mut failed = false;
+    for file in files {
+        let matches = match grep_file(&pattern, file) {
+            Ok(matches) => matches,
+            Err(e) => {
+                eprintln!("sxgrep: {}: {}", file, e);
+                failed = true;
+                continue;
+            }
+        };
+        found |= !matches.is_empty();
+        for line in report(&mode, &pf, file, matches) {
+            println!("{}", line);
+        }
+    }
+
+    match (failed, found) {
+        (true, _) => ExitCode::from(2),
+        (false, true) => ExitCode::SUCCESS,
+        (false, false) => ExitCode::from(1),
+    }
+}
+
+fn grep_file(
+    pattern: &TreePattern<()>,
+    file: &str,
+) -> Result<Vec<Match>, Box<dyn std::error::Error>> {
+    let src = std::fs::read_to_string(file)?;
+    let (forms, lines) = read_all_with_lines(&src)?;
+    let lines: HashMap<_, _> = lines.into_iter().collect();
+    let mut matches = vec![];
+    PrettyExpr::list(forms).walk(&mut |path, x| {
+        if !path.is_empty() && pattern.matches(x).is_some() {
+            matches.push(Match {
+                path: path.to_vec(),
+                line: lines[path],
+                expr: x.clone(),
+            });
+        }
+        true
+    });
+    Ok(matches)
+}
+
+fn report(mode: &Mode, pf: &PrettyFormatter, file: &str, matches: Vec<Match>) -> Vec<String> {
+    match mode {
+        Mode::Count => vec![format!("{}:{}", file, matches.len())],
+        Mode::Paths => matches
+            .iter()
+            .map(|m| format!("{}:{}", file, format_path(&m.path)))
+            .collect(),
+        Mode::Print => matches
+            .into_iter()
+            .map(|m| format!("{}:{}: {}", file, m.line, pf.pretty(m.expr)))
+            .collect(),
+    }
+}
+
+#[test]
+fn tests() {
+    let file = std::env::temp_dir().join(format!("sxgrep-test-{}.scm", std::process::id()));
+    std::fs::write(
+        &file,
+        "; header\n(define (f x)\n  '(g 1)\n  (g x))\n(g [a . b])\n",
+    )
+    .unwrap();
+    let file = file.to_str().unwrap();
+    let pattern = TreePattern::new("(g ?x)").unwrap();
+    let pf = PrettyFormatter::new(WIDTH, 2);
+    let grep = |mode| report(&mode, &pf, file, grep_file(&pattern, file).unwrap());
+
+    assert_eq!(
+        grep(Mode::Print),
+        vec![
+            format!("{}:3: (g 1)", file),
+            format!("{}:4: (g x)", file),
+            format!("{}:5: (g [a . b])", file),
+        ]
+    );
+    assert_eq!(grep(Mode::Count), vec![format!("{}:3", file)]);
+    assert_eq!(
+        grep(Mode::Paths),
+        vec![
+            format!("{}:1.2.0", file),
+            format!("{}:1.3", file),
+            format!("{}:2", file),
+        ]
+    );
+    assert!(grep_file(&pattern, "/nonexistent/sxgrep.scm").is_err());
+    std::fs::remove_file(file).unwrap();
+}
@@ -0,0 +1,207 @@
+use crate::completion::complete;
+use crate::events::Event;
+use crate::prompt::Prompt;
+use crate::sexpr_view::SexprView;
+use crate::styles::RAINBOW;
+use crate::sxfmt::parse_path;
+use crate::EventHandler;
+
+#[derive(Copy, Clone)]
+pub enum Action {
+    Event(Event),
+    Run(fn(&mut SexprView, &str) -> Result<(), String>),
+}
+
+pub struct Command {
+    pub name: &'static str,
+    pub argument: Option<&'static str>,
+    pub action: Action,
+}
+
+impl Command {
+    const fn event(name: &'static str, event: Event) -> Self {
+        Command {
+            name,
+            argument: None,
+            action: Action::Event(event),
+        }
+    }
+
+    const fn run(
+        name: &'static str,
+        argument: Option<&'static str>,
+        run: fn(&mut SexprView, &str) -> Result<(), String>,
+    ) -> Self {
+        Command {
+            name,
+            argument,
+            action: Action::Run(run),
+        }
+    }
+}
+
+pub const COMMANDS: [Command; 16] = [
+    Command::run("save", None, save),
+    Command::run("save-as", Some("path"), save_as),
+    Command::run("goto", Some("path"), goto),
+    Command::run("theme", Some("rainbow|plain"), theme),
+    Command::run("reformat", None, reformat),
+    Command::run("fold-to-depth", Some("depth"), fold_to_depth),
+    Command::event("unfold-all", Event::UnfoldAll),
+    Command::event("toggle-fold", Event::ToggleFold),
+    Command::event("search", Event::Search),
+    Command::event("replace", Event::Replace),
+    Command::event("rename", Event::Rename),
+    Command::event("definition", Event::NavDefinition),
+    Command::event("back", Event::NavBack),
+    Command::event("expand-snippet", Event::EditSnippet),
+    Command::event("toggle-modal", Event::ToggleModal),
+    Command::event("toggle-quoted-occurrences", Event::ToggleQuotedOccurrences),
+];
+
+pub fn find(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+pub fn parse(line: &str) -> Option<(&'static Command, &str)> {
+    let line = line.trim();
+    let (name, argument) = line.split_once(' ').unwrap_or((line, ""));
+    Some((find(name)?, argument.trim()))
+}
+
+fn save(view: &mut SexprView, _: &str) -> Result<(), String> {
+    view.save().map_err(|e| e.to_string())
+}
+
+fn save_as(view: &mut SexprView, path: &str) -> Result<(), String> {
+    view.save_as(path).map_err(|e| e.to_string())
+}
+
+fn goto(view: &mut SexprView, path: &str) -> Result<(), String> {
+    match parse_path(path) {
+        Some(path) if view.is_valid_cursor(&path) => view.jump_to(path),
+        _ => return Err(format!("invalid path {:?}", path)),
+    }
+    Ok(())
+}
+
+fn theme(view: &mut SexprView, name: &str) -> Result<(), String> {
+    match name {
+        "rainbow" => view.set_delimiter_palette(RAINBOW.to_vec()),
+        "plain" => view.set_delimiter_palette(vec![]),
+        _ => return Err(format!("unknown theme {:?}", name)),
+    }
+    Ok(())
+}
+
+fn reformat(view: &mut SexprView, _: &str) -> Result<(), String> {
+    view.reformat();
+    Ok(())
+}
+
+fn fold_to_depth(view: &mut SexprView, depth: &str) -> Result<(), String> {
+    match depth.parse() {
+        Ok(depth) => view.fold_to_depth(depth),
+        Err(_) => return Err(format!("invalid depth {:?}", depth)),
+    }
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct Palette {
+    pub prompt: Prompt,
+    pub items: Vec<String>,
+    pub selected: usize,
+    history: Vec<String>,
+    history_index: usize,
+}
+
+impl Palette {
+    pub fn new(text: &str, history: Vec<String>) -> Self {
+        let mut palette = Palette {
+            prompt: Prompt::new(": "),
+            items: vec![],
+            selected: 0,
+            history_index: history.len(),
+            history,
+        };
+        palette.set_text(text);
+        palette
+    }
+
+    fn set_text(&mut self, text: &str) {
+        self.prompt.set_text(text);
+        self.update();
+    }
+
+    fn update(&mut self) {
+        let text = self.prompt.text();
+        self.selected = 0;
+        self.items = match text.contains(' ') {
+            true => vec![],
+            false => {
+                let names: Vec<_> = COMMANDS.iter().map(|c| c.name.to_string()).collect();
+                complete(&text, &names)
+            }
+        };
+    }
+
+    pub fn command_line(&self) -> String {
+        let text = self.prompt.text();
+        match self.items.get(self.selected) {
+            Some(item) if find(text.trim()).is_none() => item.clone(),
+            _ => text,
+        }
+    }
+
+    fn recall(&mut self, dir: isize) {
+        let index = self.history_index as isize + dir;
+        if index < 0 || index as usize > self.history.len() {
+            return;
+        }
+        self.history_index = index as usize;
+        let text = self.history.get(self.history_index).cloned();
+        self.set_text(&text.unwrap_or_default());
+    }
+}
+
+impl EventHandler<Event> for Palette {
+    fn handle_event(&mut self, event: &Event) -> bool {
+        let n = self.items.len();
+        match event {
+            Event::Cycle | Event::CycleBack if n == 0 => {}
+            Event::Cycle => self.selected = (self.selected + 1) % n,
+            Event::CycleBack => self.selected = (self.selected + n - 1) % n,
+            Event::NavUp => self.recall(-1),
+            Event::NavDown => self.recall(1),
+            _ if self.prompt.handle_event(event) => self.update(),
+            _ => return false,
+        }
+        true
+    }
+}
+
+#[test]
+fn tests() {
+    assert_eq!(
+        parse(" goto  0.1 ").map(|(c, arg)| (c.name, arg)),
+        Some(("goto", "0.1"))
+    );
+    assert_eq!(
+        parse("save").map(|(c, arg)| (c.name, arg)),
+        Some(("save", ""))
+    );
+    assert!(parse("fly away").is_none());
+
+    let mut palette = Palette::new("sav", vec!["goto 0".to_string()]);
+    assert_eq!(palette.items, vec!["save", "save-as"]);
+    assert_eq!(palette.command_line(), "save");
+    palette.handle_event(&Event::Cycle);
+    assert_eq!(palette.command_line(), "save-as");
+    palette.handle_event(&Event::NavUp);
+    assert_eq!(palette.command_line(), "goto 0");
+    assert!(palette.items.is_empty());
+    palette.handle_event(&Event::NavDown);
+    assert_eq!(palette.prompt.text(), "");
+    assert_eq!(palette.items.len(), COMMANDS.len());
+}
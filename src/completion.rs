@@ -0,0 +1,86 @@
+pub const BUILTINS: [&str; 24] = [
+    "append",
+    "apply",
+    "assoc",
+    "car",
+    "cdr",
+    "cond",
+    "cons",
+    "display",
+    "else",
+    "eq?",
+    "equal?",
+    "filter",
+    "for-each",
+    "length",
+    "list",
+    "map",
+    "member",
+    "newline",
+    "not",
+    "null?",
+    "pair?",
+    "reverse",
+    "string-append",
+    "vector",
+];
+
+pub fn fuzzy_score(pattern: &str, candidate: &str) -> Option<usize> {
+    let mut score = 0;
+    let mut rest = candidate.char_indices();
+    let mut last = 0;
+    for ch in pattern.chars() {
+        let (i, _) = rest.find(|&(_, c)| c == ch)?;
+        score += i - last;
+        last = i + 1;
+    }
+    Some(score + candidate.len() - last)
+}
+
+pub fn complete(prefix: &str, candidates: &[String]) -> Vec<String> {
+    let mut seen = vec![];
+    let mut prefixed = vec![];
+    let mut fuzzy = vec![];
+    for candidate in candidates {
+        if candidate == prefix || seen.contains(&candidate) {
+            continue;
+        }
+        seen.push(candidate);
+        if candidate.starts_with(prefix) {
+            prefixed.push(candidate.clone());
+        } else if let Some(score) = fuzzy_score(prefix, candidate) {
+            fuzzy.push((score, candidate.clone()));
+        }
+    }
+    fuzzy.sort();
+    prefixed
+        .into_iter()
+        .chain(fuzzy.into_iter().map(|(_, c)| c))
+        .collect()
+}
+
+#[test]
+fn tests() {
+    let candidates: Vec<String> = ["filter", "fold", "first", "f", "buffer-fill", "fold"]
+        .iter()
+        .map(|s| s.to_string())
+        .collect();
+
+    assert_eq!(
+        complete("fi", &candidates),
+        vec!["filter", "first", "buffer-fill"]
+    );
+    assert_eq!(
+        complete("fl", &candidates),
+        vec!["fold", "filter", "buffer-fill"]
+    );
+    assert_eq!(
+        complete("f", &candidates),
+        vec!["filter", "fold", "first", "buffer-fill"]
+    );
+    assert!(complete("xyz", &candidates).is_empty());
+
+    assert_eq!(fuzzy_score("abc", "abc"), Some(0));
+    assert_eq!(fuzzy_score("ac", "abc"), Some(1));
+    assert_eq!(fuzzy_score("ca", "abc"), None);
+}
@@ -1,3 +1,6 @@
+use crate::commands;
+use crate::sxfmt::{Delimiter, Prefix};
+
 #[derive(Debug, Copy, Clone)]
 pub enum Event {
     Unknown,
@@ -6,8 +9,238 @@ pub enum Event {
     EditDelete,
     EditWrap,
     EditUnwrap,
+    EditDelimiter(Delimiter),
+    EditPrefix(Prefix),
+    EditSnippet,
+    EditIndent,
+    EditDragUp,
+    EditDragDown,
+    EditPromote,
+    EditDemote,
+    ToggleFold,
+    FoldToDepth(usize),
+    UnfoldAll,
+    SelectUp,
+    SelectDown,
+    SelectParent,
+    ExpandRegion,
+    ShrinkRegion,
+    Cut,
+    Copy,
+    Paste,
     NavLeft,
     NavRight,
     NavUp,
     NavDown,
+    NavHome,
+    NavEnd,
+    NavNextSibling,
+    NavPrevSibling,
+    NavFirstSibling,
+    NavLastSibling,
+    NavLastChild,
+    NavTopLevel,
+    NavNextList,
+    NavPrevList,
+    NavNextOccurrence,
+    NavPrevOccurrence,
+    ToggleQuotedOccurrences,
+    NavNextMatch,
+    NavPrevMatch,
+    NavDefinition,
+    NavBack,
+    Search,
+    Replace,
+    Rename,
+    ToggleModal,
+    Cycle,
+    CycleBack,
+    Confirm,
+    Cancel,
+    Save,
+    MacroRecord,
+    MacroPlay,
+    Palette,
+    Command(&'static str),
+}
+
+impl Event {
+    pub fn is_navigation(&self) -> bool {
+        matches!(
+            self,
+            Event::NavLeft
+                | Event::NavRight
+                | Event::NavUp
+                | Event::NavDown
+                | Event::NavHome
+                | Event::NavEnd
+                | Event::NavNextSibling
+                | Event::NavPrevSibling
+                | Event::NavFirstSibling
+                | Event::NavLastSibling
+                | Event::NavLastChild
+                | Event::NavTopLevel
+                | Event::NavNextList
+                | Event::NavPrevList
+                | Event::NavNextOccurrence
+                | Event::NavPrevOccurrence
+                | Event::NavNextMatch
+                | Event::NavPrevMatch
+                | Event::NavDefinition
+                | Event::NavBack
+                | Event::Cycle
+                | Event::CycleBack
+        )
+    }
+}
+
+impl std::str::FromStr for Event {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let error = || format!("unknown event: {}", text);
+        if let Some((name, arg)) = text.strip_suffix(')').and_then(|t| t.split_once('(')) {
+            return match name {
+                "Edit" => parse_char(arg).map(Event::Edit),
+                "EditDelimiter" => parse_delimiter(arg).map(Event::EditDelimiter),
+                "EditPrefix" => parse_prefix(arg).map(Event::EditPrefix),
+                "FoldToDepth" => arg.parse().ok().map(Event::FoldToDepth),
+                "Command" => arg
+                    .strip_prefix('"')
+                    .and_then(|name| name.strip_suffix('"'))
+                    .and_then(commands::find)
+                    .map(|command| Event::Command(command.name)),
+                _ => None,
+            }
+            .ok_or_else(error);
+        }
+        Ok(match text {
+            "Unknown" => Event::Unknown,
+            "EditBackspace" => Event::EditBackspace,
+            "EditDelete" => Event::EditDelete,
+            "EditWrap" => Event::EditWrap,
+            "EditUnwrap" => Event::EditUnwrap,
+            "EditSnippet" => Event::EditSnippet,
+            "EditIndent" => Event::EditIndent,
+            "EditDragUp" => Event::EditDragUp,
+            "EditDragDown" => Event::EditDragDown,
+            "EditPromote" => Event::EditPromote,
+            "EditDemote" => Event::EditDemote,
+            "ToggleFold" => Event::ToggleFold,
+            "UnfoldAll" => Event::UnfoldAll,
+            "SelectUp" => Event::SelectUp,
+            "SelectDown" => Event::SelectDown,
+            "SelectParent" => Event::SelectParent,
+            "ExpandRegion" => Event::ExpandRegion,
+            "ShrinkRegion" => Event::ShrinkRegion,
+            "Cut" => Event::Cut,
+            "Copy" => Event::Copy,
+            "Paste" => Event::Paste,
+            "NavLeft" => Event::NavLeft,
+            "NavRight" => Event::NavRight,
+            "NavUp" => Event::NavUp,
+            "NavDown" => Event::NavDown,
+            "NavHome" => Event::NavHome,
+            "NavEnd" => Event::NavEnd,
+            "NavNextSibling" => Event::NavNextSibling,
+            "NavPrevSibling" => Event::NavPrevSibling,
+            "NavFirstSibling" => Event::NavFirstSibling,
+            "NavLastSibling" => Event::NavLastSibling,
+            "NavLastChild" => Event::NavLastChild,
+            "NavTopLevel" => Event::NavTopLevel,
+            "NavNextList" => Event::NavNextList,
+            "NavPrevList" => Event::NavPrevList,
+            "NavNextOccurrence" => Event::NavNextOccurrence,
+            "NavPrevOccurrence" => Event::NavPrevOccurrence,
+            "ToggleQuotedOccurrences" => Event::ToggleQuotedOccurrences,
+            "NavNextMatch" => Event::NavNextMatch,
+            "NavPrevMatch" => Event::NavPrevMatch,
+            "NavDefinition" => Event::NavDefinition,
+            "NavBack" => Event::NavBack,
+            "Search" => Event::Search,
+            "Replace" => Event::Replace,
+            "Rename" => Event::Rename,
+            "ToggleModal" => Event::ToggleModal,
+            "Cycle" => Event::Cycle,
+            "CycleBack" => Event::CycleBack,
+            "Confirm" => Event::Confirm,
+            "Cancel" => Event::Cancel,
+            "Save" => Event::Save,
+            "MacroRecord" => Event::MacroRecord,
+            "MacroPlay" => Event::MacroPlay,
+            "Palette" => Event::Palette,
+            _ => return Err(error()),
+        })
+    }
+}
+
+fn parse_char(text: &str) -> Option<char> {
+    let text = text.strip_prefix('\'')?.strip_suffix('\'')?;
+    let mut chars = text.chars();
+    let ch = match (chars.next()?, chars.next()) {
+        ('\\', Some('n')) => '\n',
+        ('\\', Some('r')) => '\r',
+        ('\\', Some('t')) => '\t',
+        ('\\', Some('0')) => '\0',
+        ('\\', Some(ch @ ('\\' | '\'' | '"'))) => ch,
+        ('\\', Some('u')) => {
+            let hex = text.strip_prefix("\\u{")?.strip_suffix('}')?;
+            return char::from_u32(u32::from_str_radix(hex, 16).ok()?);
+        }
+        (ch, None) => return Some(ch),
+        _ => return None,
+    };
+    chars.next().is_none().then_some(ch)
+}
+
+fn parse_delimiter(text: &str) -> Option<Delimiter> {
+    match text {
+        "List" => Some(Delimiter::List),
+        "Vector" => Some(Delimiter::Vector),
+        "Map" => Some(Delimiter::Map),
+        "Set" => Some(Delimiter::Set),
+        _ => None,
+    }
+}
+
+fn parse_prefix(text: &str) -> Option<Prefix> {
+    match text {
+        "Quote" => Some(Prefix::Quote),
+        "Quasiquote" => Some(Prefix::Quasiquote),
+        "Unquote" => Some(Prefix::Unquote),
+        "UnquoteSplicing" => Some(Prefix::UnquoteSplicing),
+        "Syntax" => Some(Prefix::Syntax),
+        "Quasisyntax" => Some(Prefix::Quasisyntax),
+        "Unsyntax" => Some(Prefix::Unsyntax),
+        "UnsyntaxSplicing" => Some(Prefix::UnsyntaxSplicing),
+        "Deref" => Some(Prefix::Deref),
+        "AnonymousFn" => Some(Prefix::AnonymousFn),
+        _ => None,
+    }
+}
+
+#[test]
+fn tests() {
+    for event in [
+        Event::Edit('x'),
+        Event::Edit('\''),
+        Event::Edit('\\'),
+        Event::Edit(' '),
+        Event::Edit('λ'),
+        Event::Edit('\u{7f}'),
+        Event::EditDelimiter(Delimiter::Set),
+        Event::EditPrefix(Prefix::UnquoteSplicing),
+        Event::FoldToDepth(3),
+        Event::NavDown,
+        Event::MacroPlay,
+        Event::Command("goto"),
+    ] {
+        let text = format!("{:?}", event);
+        assert_eq!(format!("{:?}", text.parse::<Event>().unwrap()), text);
+    }
+    assert!("Edit('ab')".parse::<Event>().is_err());
+    assert!("Fly".parse::<Event>().is_err());
+    assert!("Command(\"fly\")".parse::<Event>().is_err());
+    assert!(Event::NavLastChild.is_navigation());
+    assert!(!Event::Cut.is_navigation());
 }
@@ -0,0 +1,186 @@
+use crate::styles::Style;
+use crate::sxfmt::PrettyExpr;
+
+const SPECIAL_FORMS: [&str; 22] = [
+    "and",
+    "begin",
+    "case",
+    "cond",
+    "def",
+    "define",
+    "defn",
+    "do",
+    "fn",
+    "if",
+    "lambda",
+    "let",
+    "let*",
+    "letrec",
+    "loop",
+    "or",
+    "quasiquote",
+    "quote",
+    "set!",
+    "unless",
+    "when",
+    "with",
+];
+
+pub fn highlight(expr: PrettyExpr<Style>) -> PrettyExpr<Style> {
+    highlight_expr(expr, false)
+}
+
+fn highlight_expr(expr: PrettyExpr<Style>, quoted: bool) -> PrettyExpr<Style> {
+    match expr {
+        PrettyExpr::Comment(_) => PrettyExpr::styled(Style::Comment, expr),
+        PrettyExpr::Hole => PrettyExpr::styled(Style::Hole, expr),
+        PrettyExpr::Atom(_) | PrettyExpr::Stat(_) if quoted => expr,
+        PrettyExpr::Atom(_) | PrettyExpr::Stat(_) => {
+            match classify_atom(expr.get_text().unwrap()) {
+                Some(style) => PrettyExpr::styled(style, expr),
+                None => expr,
+            }
+        }
+        PrettyExpr::Quote(q, x) if quoted && q.is_unquoting() => PrettyExpr::styled(
+            Style::Default,
+            PrettyExpr::prefixed(q, highlight_expr(*x, false)),
+        ),
+        PrettyExpr::Quote(q, x) if !quoted && q.is_quoting() => PrettyExpr::styled(
+            Style::Quoted,
+            PrettyExpr::prefixed(q, highlight_expr(*x, true)),
+        ),
+        PrettyExpr::Quote(q, x) => PrettyExpr::prefixed(q, highlight_expr(*x, quoted)),
+        PrettyExpr::Dotted(x) => PrettyExpr::dotted(highlight_expr(*x, quoted)),
+        PrettyExpr::Inline(d, xs) => PrettyExpr::Inline(d, highlight_list(xs, quoted)),
+        PrettyExpr::Expand(d, xs) => PrettyExpr::Expand(d, highlight_list(xs, quoted)),
+        PrettyExpr::Style(s, x) => PrettyExpr::styled(s, highlight_expr(*x, quoted)),
+        PrettyExpr::Folded(x) => PrettyExpr::Folded(Box::new(highlight_expr(*x, quoted))),
+    }
+}
+
+fn highlight_list(xs: Vec<PrettyExpr<Style>>, quoted: bool) -> Vec<PrettyExpr<Style>> {
+    let mut xs = xs.into_iter();
+    let head = xs.next().map(|x| highlight_head(x, quoted));
+    head.into_iter()
+        .chain(xs.map(|x| highlight_expr(x, quoted)))
+        .collect()
+}
+
+fn highlight_head(x: PrettyExpr<Style>, quoted: bool) -> PrettyExpr<Style> {
+    match x {
+        PrettyExpr::Style(s, x) => PrettyExpr::styled(s, highlight_head(*x, quoted)),
+        x if !quoted && is_special_form(&x) => PrettyExpr::styled(Style::SpecialForm, x),
+        x => highlight_expr(x, quoted),
+    }
+}
+
+fn is_special_form(x: &PrettyExpr<Style>) -> bool {
+    x.is_atom()
+        && !x.is_comment()
+        && x.get_text()
+            .map(|text| SPECIAL_FORMS.contains(&text))
+            .unwrap_or(false)
+}
+
+fn classify_atom(text: &str) -> Option<Style> {
+    let mut chars = text.chars();
+    match (chars.next()?, chars.next()) {
+        ('"', _) => Some(Style::StringLiteral),
+        (':', _) | ('#', Some(':')) => Some(Style::Keyword),
+        ('#', Some('x' | 'b' | 'o' | 'd' | 'e' | 'i')) => Some(Style::Number),
+        _ if is_number(text) => Some(Style::Number),
+        _ => None,
+    }
+}
+
+fn is_number(text: &str) -> bool {
+    let unsigned = text.strip_prefix(['+', '-']).unwrap_or(text);
+    if !unsigned.starts_with(|c: char| c.is_ascii_digit() || c == '.') {
+        return false;
+    }
+    match unsigned.split_once('/') {
+        Some((n, d)) => n.parse::<u64>().is_ok() && d.parse::<u64>().is_ok(),
+        None => unsigned.parse::<f64>().is_ok(),
+    }
+}
+
+#[test]
+fn numbers() {
+    for text in ["1", "-1", "+1.5", ".5", "1e10", "1/2", "#x1F"] {
+        assert_eq!(classify_atom(text), Some(Style::Number), "{text}");
+    }
+    for text in [
+        "1+",
+        "1-",
+        "+",
+        "-",
+        "...",
+        "1/x",
+        "-inf",
+        "nan",
+        "2d-vector",
+    ] {
+        assert_eq!(classify_atom(text), None, "{text}");
+    }
+}
+
+#[test]
+fn highlight_styles() {
+    fn styled_nodes(x: &PrettyExpr<Style>, out: &mut Vec<(Style, String)>) {
+        match x {
+            PrettyExpr::Style(s, inner) => {
+                out.push((*s, inner.to_string()));
+                styled_nodes(inner, out);
+            }
+            PrettyExpr::Quote(_, inner) | PrettyExpr::Dotted(inner) | PrettyExpr::Folded(inner) => {
+                styled_nodes(inner, out)
+            }
+            PrettyExpr::Inline(_, xs) | PrettyExpr::Expand(_, xs) => {
+                xs.iter().for_each(|x| styled_nodes(x, out))
+            }
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => {}
+        }
+    }
+
+    fn highlighted(src: &str) -> Vec<(Style, String)> {
+        let mut out = vec![];
+        for x in crate::reader::read_all(src).unwrap() {
+            styled_nodes(&highlight(x), &mut out);
+        }
+        out.into_iter()
+            .map(|(s, text)| (s, text.trim_end().to_string()))
+            .collect()
+    }
+
+    let s = |style, text: &str| (style, text.to_string());
+    assert_eq!(
+        highlighted("(if (1+ x) \"yes\" :no) ; done"),
+        vec![
+            s(Style::SpecialForm, "if"),
+            s(Style::StringLiteral, "\"yes\""),
+            s(Style::Keyword, ":no"),
+            s(Style::Comment, "; done"),
+        ]
+    );
+    assert_eq!(
+        highlighted("(f 42 '(if 1 \"a\") `(a ,(g 2)))"),
+        vec![
+            s(Style::Number, "42"),
+            s(Style::Quoted, "'(if 1 \"a\")"),
+            s(Style::Quoted, "`(a ,(g 2))"),
+            s(Style::Default, ",(g 2)"),
+            s(Style::Number, "2"),
+        ]
+    );
+    assert_eq!(
+        highlighted("(let ((x 1)) (quote x))"),
+        vec![
+            s(Style::SpecialForm, "let"),
+            s(Style::Number, "1"),
+            s(Style::SpecialForm, "quote"),
+        ]
+    );
+}
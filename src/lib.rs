@@ -0,0 +1,7 @@
+#[macro_use]
+pub mod sxfmt;
+pub mod completion;
+pub mod reader;
+pub mod rewrite;
+pub mod scope;
+pub mod snippet;
@@ -0,0 +1,160 @@
+use crate::events::Event;
+use crate::sexpr_view::SexprView;
+use crate::EventHandler;
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+
+const PLAY_LIMIT: usize = 1000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Repeat {
+    Times(usize),
+    UntilFailure,
+}
+
+enum Pending {
+    Record,
+    Play(Option<Repeat>),
+}
+
+pub struct Macros {
+    file: Option<PathBuf>,
+    named: BTreeMap<char, Vec<Event>>,
+    recording: Option<(char, Vec<Event>)>,
+    pending: Option<Pending>,
+    playback: Option<(Vec<Event>, Repeat)>,
+}
+
+impl Macros {
+    pub fn load(file: Option<PathBuf>) -> std::io::Result<Self> {
+        let mut named = BTreeMap::new();
+        if let Some(path) = file.as_ref().filter(|path| path.exists()) {
+            let mut register = None;
+            for line in std::fs::read_to_string(path)?.lines() {
+                if let Some(name) = line.strip_prefix('@') {
+                    register = name.chars().next();
+                    named.extend(register.map(|r| (r, vec![])));
+                } else if let Some(events) = register.and_then(|r| named.get_mut(&r)) {
+                    let event = line
+                        .parse()
+                        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+                    events.push(event);
+                }
+            }
+        }
+        Ok(Macros {
+            file,
+            named,
+            recording: None,
+            pending: None,
+            playback: None,
+        })
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        let path = match &self.file {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let mut text = String::new();
+        for (register, events) in &self.named {
+            text += &format!("@{}\n", register);
+            for event in events {
+                text += &format!("{:?}\n", event);
+            }
+        }
+        std::fs::write(path, text)
+    }
+
+    pub fn take_playback(&mut self) -> Option<(Vec<Event>, Repeat)> {
+        self.playback.take()
+    }
+
+    pub fn handle_event(&mut self, event: &Event) -> std::io::Result<bool> {
+        match (self.pending.take(), event) {
+            (Some(_), Event::Cancel) => return Ok(true),
+            (Some(Pending::Record), Event::Edit(ch)) => {
+                self.recording = Some((*ch, vec![]));
+                return Ok(true);
+            }
+            (Some(Pending::Play(repeat)), Event::Edit('*')) if repeat.is_none() => {
+                self.pending = Some(Pending::Play(Some(Repeat::UntilFailure)));
+                return Ok(true);
+            }
+            (Some(Pending::Play(repeat)), Event::Edit(ch @ '0'..='9')) => {
+                let digit = *ch as usize - '0' as usize;
+                let n = match repeat {
+                    Some(Repeat::Times(n)) => (n * 10 + digit).min(PLAY_LIMIT),
+                    _ => digit,
+                };
+                self.pending = Some(Pending::Play(Some(Repeat::Times(n))));
+                return Ok(true);
+            }
+            (Some(Pending::Play(repeat)), Event::Edit(ch)) => {
+                if let Some(events) = self.named.get(ch) {
+                    let repeat = repeat.unwrap_or(Repeat::Times(1));
+                    self.playback = Some((events.clone(), repeat));
+                }
+                return Ok(true);
+            }
+            (Some(_), _) => return Ok(true),
+            (None, _) => {}
+        }
+        match event {
+            Event::MacroRecord => match self.recording.take() {
+                Some((register, events)) => {
+                    self.named.insert(register, events);
+                    self.save()?;
+                }
+                None => self.pending = Some(Pending::Record),
+            },
+            Event::MacroPlay if self.recording.is_none() => {
+                self.pending = Some(Pending::Play(None));
+            }
+            Event::MacroPlay => {}
+            Event::Unknown => return Ok(false),
+            event => {
+                if let Some((_, events)) = &mut self.recording {
+                    events.push(*event);
+                }
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+pub fn play(view: &mut SexprView, events: &[Event], repeat: Repeat) -> Result<(), String> {
+    let times = match repeat {
+        Repeat::Times(n) => n,
+        Repeat::UntilFailure if events.iter().any(Event::is_navigation) => PLAY_LIMIT,
+        Repeat::UntilFailure => return Err("macro has no movement to stop at; give a count".into()),
+    };
+    for _ in 0..times {
+        for event in events {
+            let before = view.position();
+            if !view.handle_event(event) {
+                return Err(format!("macro stopped at unhandled {:?}", event));
+            }
+            if event.is_navigation() && view.position() == before {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn tests() {
+    let mut view = SexprView::new(crate::reader::read_all("(a b c)").unwrap(), 40, 10);
+    view.set_cursor(vec![0, 0]);
+    assert!(play(&mut view, &[Event::Edit('x')], Repeat::UntilFailure).is_err());
+    assert!(!view.is_modified());
+    assert!(play(&mut view, &[Event::Save], Repeat::Times(1)).is_ok());
+    assert!(play(&mut view, &[Event::Unknown], Repeat::Times(3)).is_err());
+    let events = [Event::Edit('x'), Event::NavNextSibling];
+    assert!(play(&mut view, &events, Repeat::UntilFailure).is_ok());
+    assert!(view.is_modified());
+    assert_eq!(view.position().0, vec![0, 2]);
+}
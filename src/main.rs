@@ -1,24 +1,32 @@
-#[macro_use]
-mod sxfmt;
+mod commands;
 mod events;
+mod highlight;
+mod macros;
+mod prompt;
+mod search;
 mod sexpr_view;
+mod status_bar;
 mod styles;
 mod terminal_backend;
 mod textbuffer;
 
 use crate::backend::{adapt_event, TextBuffer};
-use crate::sxfmt::{Formatter, PrettyExpr, PrettyFormatter};
+use crate::sxfmt::{parse_path, Formatter, PrettyExpr, PrettyFormatter};
 use crate::terminal_backend as backend;
 use crate::textbuffer::RenderTarget;
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 use crossterm::{
     cursor,
-    event::{read, Event, KeyCode, KeyEvent},
+    event::{poll, read, Event, KeyCode, KeyEvent},
     execute, terminal, ErrorKind, Result,
 };
+use macros::Macros;
 use sexpr_view::SexprView;
+use status_bar::StatusBar;
 use std::io::stdout;
+use std::path::PathBuf;
 use styles::Style;
+use term_experiment::{completion, pe, reader, rewrite, scope, snippet, sxfmt};
 
 pub trait Item {
     fn size(&self) -> (usize, usize);
@@ -30,6 +38,8 @@ pub trait EventHandler<E> {
     fn handle_event(&mut self, event: &E) -> bool;
 }
 
+const MACRO_FILE: &str = ".term-experiment-macros";
+
 const DEFAULT_FRAME: [char; 9] = ['╔', '═', '╗', '║', ' ', '║', '╚', '═', '╝'];
 
 struct Framed<T: Item> {
@@ -90,22 +100,32 @@ struct TextBufferFormatter<'a> {
     buf: &'a mut TextBuffer,
     current_style: Style,
     saved_styles: Vec<Style>,
+    palette: &'a [Style],
     start_column: usize,
     current_row: usize,
+    limit: (usize, usize),
     cursor: (usize, usize),
+    cursor_position: Option<(usize, usize)>,
 }
 
 impl<'a> TextBufferFormatter<'a> {
-    pub fn new(buf: &'a mut TextBuffer, x: usize, y: usize) -> Self {
+    pub fn new(buf: &'a mut TextBuffer, x: usize, y: usize, width: usize, height: usize) -> Self {
         TextBufferFormatter {
             buf,
             current_style: Default::default(),
             saved_styles: vec![],
+            palette: &[],
             start_column: x,
             current_row: y,
+            limit: (x + width, y + height),
             cursor: (x, y),
+            cursor_position: None,
         }
     }
+
+    pub fn with_palette(self, palette: &'a [Style]) -> Self {
+        TextBufferFormatter { palette, ..self }
+    }
 }
 
 impl<'a> Formatter<Style> for TextBufferFormatter<'a> {
@@ -113,14 +133,19 @@ impl<'a> Formatter<Style> for TextBufferFormatter<'a> {
 
     fn write(&mut self, x: impl std::fmt::Display) -> std::result::Result<(), Self::Error> {
         for ch in x.to_string().chars() {
-            self.buf
-                .set_char(self.cursor.0, self.cursor.1, ch, self.current_style);
+            if self.cursor.0 < self.limit.0 && self.cursor.1 < self.limit.1 {
+                self.buf
+                    .set_char(self.cursor.0, self.cursor.1, ch, self.current_style);
+            }
             self.cursor.0 += 1;
         }
         Ok(())
     }
 
     fn set_style(&mut self, style: &Style) {
+        if matches!(style, Style::Editing | Style::Highlight) && self.cursor_position.is_none() {
+            self.cursor_position = Some(self.cursor);
+        }
         self.current_style = *style;
     }
 
@@ -133,6 +158,31 @@ impl<'a> Formatter<Style> for TextBufferFormatter<'a> {
         self.set_style(&style);
     }
 
+    fn write_delimiter(
+        &mut self,
+        delimiter: &str,
+        depth: usize,
+    ) -> std::result::Result<(), Self::Error> {
+        let delimiter_style = match self.current_style {
+            Style::Enclosing => Style::MatchingDelimiter,
+            Style::Default if !self.palette.is_empty() => self.palette[depth % self.palette.len()],
+            _ => return self.write(delimiter),
+        };
+        let style = self.current_style;
+        self.current_style = delimiter_style;
+        self.write(delimiter)?;
+        self.current_style = style;
+        Ok(())
+    }
+
+    fn write_fold_marker(&mut self, marker: &str) -> std::result::Result<(), Self::Error> {
+        let style = self.current_style;
+        self.current_style = Style::FoldMarker;
+        self.write(marker)?;
+        self.current_style = style;
+        Ok(())
+    }
+
     fn write_newline(&mut self) -> std::result::Result<(), Self::Error> {
         self.current_row += 1;
         self.cursor = (self.start_column, self.current_row);
@@ -148,28 +198,95 @@ fn main() -> Result<()> {
 
     let (w, h) = terminal::size()?;
     let mut buffer: TextBuffer = TextBuffer::new(w as usize, h as usize);
+    let mut height = h as usize;
+    let mut status_bar = StatusBar::new(w as usize);
 
-    let exp = pe![(let ((a 1) (b 2) (c 3)) ("+" a b))];
+    let mut sxv = match std::env::args().nth(1) {
+        Some(path) => {
+            let mut sxv = match SexprView::load(&path, 25, 10) {
+                Ok(sxv) => sxv,
+                Err(e) => {
+                    status_bar.show_message(format!("could not open {}: {}", path, e));
+                    let mut sxv = SexprView::new(vec![], 25, 10);
+                    sxv.set_file(&path);
+                    sxv
+                }
+            };
+            sxv.resize(w as usize - 7, h as usize - 5);
+            if let Some(path) = std::env::args().nth(2).and_then(|p| parse_path(&p)) {
+                sxv.set_cursor(path);
+            }
+            sxv
+        }
+        None => {
+            let exp = pe![(let ((a 1) (b 2) (c 3)) ("+" a b))];
+            SexprView::new(vec![exp], 25, 10)
+        }
+    };
+    if std::env::var_os("NO_COLOR").is_some() {
+        sxv.set_delimiter_palette(vec![]);
+    }
 
-    let mut sxv = SexprView::new(exp, 25, 10);
+    let macro_file = std::env::var_os("HOME").map(|home| PathBuf::from(home).join(MACRO_FILE));
+    let mut macros = match Macros::load(macro_file) {
+        Ok(macros) => macros,
+        Err(e) => {
+            status_bar.show_message(format!("could not load macros: {}", e));
+            Macros::load(None)?
+        }
+    };
 
     loop {
+        if let Some(message) = sxv.take_message() {
+            status_bar.show_message(message);
+        }
+        status_bar.set_status(sxv.status());
+
         buffer.clear('╳', Style::Background);
 
         Framed::new(sxv.clone()).draw(&mut buffer, 2, 1)?;
+        status_bar.draw(&mut buffer, 0, height - 1)?;
 
         buffer.render(&mut stdout)?;
 
+        if let Some(timeout) = status_bar.next_update() {
+            if !poll(timeout)? {
+                status_bar.expire_message();
+                continue;
+            }
+        }
+
         let event = read()?;
-        if !sxv.handle_event(&adapt_event(event)) {
-            match event {
-                Event::Resize(w, h) => {
+        let adapted = adapt_event(event);
+        match macros.handle_event(&adapted) {
+            Ok(true) => {
+                if let Some((events, repeat)) = macros.take_playback() {
+                    if let Err(e) = macros::play(&mut sxv, &events, repeat) {
+                        status_bar.show_message(e);
+                    }
+                }
+                continue;
+            }
+            Ok(false) => {}
+            Err(e) => {
+                status_bar.show_message(format!("could not save macros: {}", e));
+                continue;
+            }
+        }
+        if !sxv.handle_event(&adapted) {
+            match (event, adapted) {
+                (Event::Resize(w, h), _) => {
                     buffer.resize(w as usize, h as usize);
+                    height = h as usize;
+                    status_bar.resize(w as usize, 1);
                     sxv.resize(w as usize - 7, h as usize - 5)
                 }
-                Event::Key(KeyEvent {
-                    code: KeyCode::Esc, ..
-                }) => break,
+                (
+                    Event::Key(KeyEvent {
+                        code: KeyCode::Esc, ..
+                    }),
+                    _,
+                ) => break,
                 _ => {}
             }
         }
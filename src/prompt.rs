@@ -0,0 +1,75 @@
+use crate::backend::TextBuffer;
+use crate::events::Event;
+use crate::styles::Style;
+use crate::EventHandler;
+
+#[derive(Clone)]
+pub struct Prompt {
+    label: String,
+    text: Vec<char>,
+    cursor: usize,
+}
+
+impl Prompt {
+    pub fn new(label: impl Into<String>) -> Self {
+        Prompt {
+            label: label.into(),
+            text: vec![],
+            cursor: 0,
+        }
+    }
+
+    pub fn text(&self) -> String {
+        self.text.iter().collect()
+    }
+
+    pub fn set_text(&mut self, text: &str) {
+        self.text = text.chars().collect();
+        self.cursor = self.text.len();
+    }
+
+    pub fn set_label(&mut self, label: impl Into<String>) {
+        self.label = label.into();
+    }
+
+    pub fn draw(&self, buf: &mut TextBuffer, x: usize, y: usize, width: usize) {
+        let line: String = self
+            .label
+            .chars()
+            .chain(self.text.iter().copied())
+            .chain(std::iter::repeat(' '))
+            .take(width)
+            .collect();
+        buf.draw_text(x, y, &line, Style::Prompt);
+        let pos = self.label.chars().count() + self.cursor;
+        if pos < width {
+            buf.set_cursor(Some((x + pos, y)));
+        }
+    }
+}
+
+impl EventHandler<Event> for Prompt {
+    fn handle_event(&mut self, event: &Event) -> bool {
+        use Event::*;
+        match event {
+            Edit(ch) => {
+                self.text.insert(self.cursor, *ch);
+                self.cursor += 1;
+            }
+            EditBackspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.text.remove(self.cursor);
+            }
+            EditDelete if self.cursor < self.text.len() => {
+                self.text.remove(self.cursor);
+            }
+            EditBackspace | EditDelete => {}
+            NavLeft => self.cursor = self.cursor.saturating_sub(1),
+            NavRight => self.cursor = usize::min(self.cursor + 1, self.text.len()),
+            NavHome => self.cursor = 0,
+            NavEnd => self.cursor = self.text.len(),
+            _ => return false,
+        }
+        true
+    }
+}
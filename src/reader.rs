@@ -0,0 +1,340 @@
+use crate::sxfmt::{Delimiter, Prefix, PrettyExpr};
+
+#[derive(Debug)]
+pub struct ReadError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ReadError {}
+
+pub fn read_all<T>(src: &str) -> Result<Vec<PrettyExpr<T>>, ReadError> {
+    read_all_with_lines(src).map(|(forms, _)| forms)
+}
+
+pub type Lines = Vec<(Vec<usize>, usize)>;
+
+pub fn read_all_with_lines<T>(src: &str) -> Result<(Vec<PrettyExpr<T>>, Lines), ReadError> {
+    let mut reader = Reader::new(src);
+    let mut forms = vec![];
+    loop {
+        reader.skip_whitespace();
+        reader.path = vec![forms.len()];
+        match reader.peek() {
+            None => return Ok((forms, reader.lines)),
+            Some(';') => forms.push(reader.read_comment()),
+            Some(ch) if is_closing(ch) => return Err(reader.error("unexpected closing delimiter")),
+            Some(_) => forms.push(reader.read_expr()?),
+        }
+    }
+}
+
+struct Reader<'a> {
+    src: &'a str,
+    pos: usize,
+    line: usize,
+    path: Vec<usize>,
+    lines: Lines,
+}
+
+impl<'a> Reader<'a> {
+    fn new(src: &'a str) -> Self {
+        Reader {
+            src,
+            pos: 0,
+            line: 1,
+            path: vec![],
+            lines: vec![],
+        }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let ch = self.peek()?;
+        self.pos += ch.len_utf8();
+        if ch == '\n' {
+            self.line += 1;
+        }
+        Some(ch)
+    }
+
+    fn push_line(&mut self) {
+        self.lines.push((self.path.clone(), self.line));
+    }
+
+    fn error(&self, message: &str) -> ReadError {
+        ReadError {
+            line: self.line,
+            message: message.to_string(),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(ch) if ch.is_whitespace()) {
+            self.next_char();
+        }
+    }
+
+    fn skip_whitespace_without_comments(&mut self, context: &str) -> Result<(), ReadError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some(';') => Err(self.error(&format!("comment not allowed {}", context))),
+            _ => Ok(()),
+        }
+    }
+
+    fn read_expr<T>(&mut self) -> Result<PrettyExpr<T>, ReadError> {
+        self.push_line();
+        if self.rest().starts_with(Delimiter::Set.open()) {
+            self.pos += Delimiter::Set.open().len();
+            return self.read_list(Delimiter::Set);
+        }
+        if let Some(prefix) = self.read_prefix() {
+            self.skip_whitespace_without_comments("after a prefix")?;
+            self.path.push(0);
+            let x = self.read_expr()?;
+            self.path.pop();
+            return Ok(PrettyExpr::prefixed(prefix, x));
+        }
+        match self.peek() {
+            Some('(') => {
+                self.next_char();
+                self.read_list(Delimiter::List)
+            }
+            Some('[') => {
+                self.next_char();
+                self.read_list(Delimiter::Vector)
+            }
+            Some('{') => {
+                self.next_char();
+                self.read_list(Delimiter::Map)
+            }
+            Some('"') => self.read_string(),
+            Some(ch) if is_closing(ch) => Err(self.error("unexpected closing delimiter")),
+            Some('.') if self.at_dot() => Err(self.error("unexpected '.' outside of list")),
+            Some(_) => Ok(self.read_atom()),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn read_prefix(&mut self) -> Option<Prefix> {
+        use Prefix::*;
+        if self.rest().starts_with("#(") {
+            self.next_char();
+            return Some(AnonymousFn);
+        }
+        let prefix = [
+            UnsyntaxSplicing,
+            UnquoteSplicing,
+            Syntax,
+            Quasisyntax,
+            Unsyntax,
+            Quote,
+            Quasiquote,
+            Unquote,
+            Deref,
+        ]
+        .into_iter()
+        .find(|p| self.rest().starts_with(p.text()))?;
+        self.pos += prefix.text().len();
+        Some(prefix)
+    }
+
+    fn read_list<T>(&mut self, delimiter: Delimiter) -> Result<PrettyExpr<T>, ReadError> {
+        let start_line = self.line;
+        let mut xs = vec![];
+        loop {
+            self.skip_whitespace();
+            self.path.push(xs.len());
+            match self.peek() {
+                None => {
+                    return Err(ReadError {
+                        line: start_line,
+                        message: format!("unclosed '{}'", delimiter.open()),
+                    })
+                }
+                Some(ch) if is_closing(ch) => {
+                    self.path.pop();
+                    self.next_char();
+                    if delimiter.close().starts_with(ch) {
+                        return Ok(PrettyExpr::bracketed(delimiter, xs));
+                    }
+                    return Err(self.error(&format!(
+                        "expected '{}' but found '{}'",
+                        delimiter.close(),
+                        ch
+                    )));
+                }
+                Some(';') => xs.push(self.read_comment()),
+                Some('.') if self.at_dot() && (xs.is_empty() || delimiter != Delimiter::List) => {
+                    self.push_line();
+                    xs.push(self.read_atom());
+                }
+                Some('.') if self.at_dot() => {
+                    self.push_line();
+                    self.next_char();
+                    self.skip_whitespace_without_comments("in a dotted tail")?;
+                    self.path.push(0);
+                    let x = self.read_expr()?;
+                    self.path.pop();
+                    xs.push(PrettyExpr::dotted(x));
+                    self.skip_whitespace_without_comments("in a dotted tail")?;
+                    if !matches!(self.peek(), Some(ch) if is_closing(ch)) {
+                        return Err(self.error("expected end of list after dotted tail"));
+                    }
+                }
+                Some(_) => xs.push(self.read_expr()?),
+            }
+            self.path.pop();
+        }
+    }
+
+    fn at_dot(&self) -> bool {
+        let mut chars = self.rest().chars();
+        chars.next() == Some('.')
+            && chars
+                .next()
+                .map(|ch| ch.is_whitespace() || is_delimiter(ch))
+                .unwrap_or(true)
+    }
+
+    fn read_string<T>(&mut self) -> Result<PrettyExpr<T>, ReadError> {
+        let start = self.pos;
+        let start_line = self.line;
+        self.next_char();
+        loop {
+            match self.next_char() {
+                Some('"') => break,
+                Some('\\') => {
+                    self.next_char();
+                }
+                Some(_) => {}
+                None => {
+                    return Err(ReadError {
+                        line: start_line,
+                        message: "unterminated string".to_string(),
+                    })
+                }
+            }
+        }
+        Ok(PrettyExpr::Atom(self.src[start..self.pos].to_string()))
+    }
+
+    fn read_comment<T>(&mut self) -> PrettyExpr<T> {
+        self.push_line();
+        let start = self.pos;
+        while !matches!(self.peek(), None | Some('\n')) {
+            self.next_char();
+        }
+        PrettyExpr::Comment(self.src[start..self.pos].trim_end().to_string())
+    }
+
+    fn read_atom<T>(&mut self) -> PrettyExpr<T> {
+        let start = self.pos;
+        if self.rest().starts_with("#\\") {
+            self.pos += 2;
+            self.next_char();
+        }
+        while let Some(ch) = self.peek() {
+            if ch.is_whitespace() || is_delimiter(ch) {
+                break;
+            }
+            self.next_char();
+        }
+        PrettyExpr::Atom(self.src[start..self.pos].to_string())
+    }
+}
+
+fn is_closing(ch: char) -> bool {
+    matches!(ch, ')' | ']' | '}')
+}
+
+fn is_delimiter(ch: char) -> bool {
+    matches!(ch, '(' | ')' | '[' | ']' | '{' | '}' | '"' | ';')
+}
+
+#[test]
+fn tests() {
+    use crate::sxfmt::PrettyFormatter;
+
+    let pf = PrettyFormatter::new(30, 2);
+    let read = |src: &str| -> Vec<String> {
+        read_all::<()>(src)
+            .unwrap()
+            .into_iter()
+            .map(|x| pf.pretty(x).to_string())
+            .collect()
+    };
+
+    assert_eq!(read("a b"), vec!["a", "b"]);
+    assert_eq!(
+        read(";; header\n(a ; comment\n b ;; end\n)"),
+        vec![";; header", "(a\n  ; comment\n  b\n  ;; end\n  )"]
+    );
+    assert_eq!(read("'(a \"b c\")"), vec!["'(a \"b c\")"]);
+    assert_eq!(
+        read("(let [x 1] {:a x}) #{1 2}"),
+        vec!["(let [x 1] {:a x})", "#{1 2}"]
+    );
+    assert_eq!(
+        read("`(a ,b ,@c) #'x #`(y #,z #,@w) @r #(+ % 1)"),
+        vec!["`(a ,b ,@c)", "#'x", "#`(y #,z #,@w)", "@r", "#(+ % 1)"]
+    );
+    assert_eq!(read("#t #\\a #\\("), vec!["#t", "#\\a", "#\\("]);
+    assert_eq!(
+        read("((a . 1) (lambda (x . rest) rest) 1.5 ...)"),
+        vec!["((a . 1)\n (lambda (x . rest) rest)\n 1.5\n ...)"]
+    );
+
+    let (forms, lines) = read_all_with_lines::<()>("; c\n(a\n 'b\n (c . d))").unwrap();
+    let mut paths = vec![];
+    PrettyExpr::list(forms).walk(&mut |path, _| {
+        paths.push(path.to_vec());
+        true
+    });
+    assert_eq!(
+        lines.iter().map(|(path, _)| path).collect::<Vec<_>>(),
+        paths[1..].iter().collect::<Vec<_>>()
+    );
+    assert_eq!(
+        lines.iter().map(|(_, line)| *line).collect::<Vec<_>>(),
+        vec![1, 2, 2, 3, 3, 4, 4, 4, 4]
+    );
+
+    assert_eq!(read_all::<()>("(a b").unwrap_err().message, "unclosed '('");
+    assert_eq!(read_all::<()>("(a\n]").unwrap_err().line, 2);
+    assert!(read_all::<()>(")").is_err());
+    assert_eq!(
+        read("(. obj method) [a . b] {a . b}"),
+        vec!["(. obj method)", "[a . b]", "{a . b}"]
+    );
+    assert!(read_all::<()>("(a . b)").unwrap()[0]
+        .get(&[1])
+        .unwrap()
+        .is_tail());
+    assert!(!read_all::<()>("[a . b]").unwrap()[0]
+        .get(&[1])
+        .unwrap()
+        .is_tail());
+    assert!(read_all::<()>("(a . b c)").is_err());
+    assert!(read_all::<()>("a . b").is_err());
+    assert_eq!(
+        read_all::<()>("' ; c\n x").unwrap_err().message,
+        "comment not allowed after a prefix"
+    );
+    assert!(read_all::<()>("(a . ; c\n b)").is_err());
+    assert!(read_all::<()>("(a . b ; c\n)").is_err());
+}
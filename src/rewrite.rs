@@ -0,0 +1,292 @@
+use crate::reader::read_all;
+use crate::sxfmt::PrettyExpr;
+use std::collections::HashMap;
+
+#[derive(Debug)]
+pub struct RuleError(pub String);
+
+impl std::fmt::Display for RuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for RuleError {}
+
+#[derive(Debug, Clone)]
+pub enum Binding<T> {
+    Node(PrettyExpr<T>),
+    Seq(Vec<PrettyExpr<T>>),
+}
+
+pub type Bindings<T> = HashMap<String, Binding<T>>;
+
+#[derive(Clone)]
+pub struct TreePattern<T>(PrettyExpr<T>);
+
+impl<T: Clone> TreePattern<T> {
+    pub fn new(src: &str) -> Result<Self, RuleError> {
+        read_one(src).map(TreePattern)
+    }
+
+    pub fn matches(&self, expr: &PrettyExpr<T>) -> Option<Bindings<T>> {
+        let mut bindings = Bindings::new();
+        match_expr(&self.0, expr, &mut bindings).then_some(bindings)
+    }
+
+    pub fn find_matches(&self, expr: &PrettyExpr<T>) -> Vec<Vec<usize>> {
+        let mut paths = vec![];
+        expr.walk(&mut |path, x| {
+            if !path.is_empty() && self.matches(x).is_some() {
+                paths.push(path.to_vec());
+            }
+            true
+        });
+        paths
+    }
+}
+
+#[derive(Clone)]
+pub struct Rule<T> {
+    pattern: TreePattern<T>,
+    template: PrettyExpr<T>,
+}
+
+impl<T: Clone> Rule<T> {
+    pub fn new(pattern: &str, template: &str) -> Result<Self, RuleError> {
+        let pattern = TreePattern::new(pattern)?;
+        let template = read_one(template)?;
+        let bound = variables(&pattern.0);
+        if let Some(name) = variables(&template)
+            .into_iter()
+            .find(|name| !bound.contains(name))
+        {
+            return Err(RuleError(format!("unbound variable ?{}", name)));
+        }
+        Ok(Rule { pattern, template })
+    }
+
+    pub fn matches(&self, expr: &PrettyExpr<T>) -> Option<Bindings<T>> {
+        self.pattern.matches(expr)
+    }
+
+    pub fn find_matches(&self, expr: &PrettyExpr<T>) -> Vec<Vec<usize>> {
+        self.pattern.find_matches(expr)
+    }
+
+    pub fn rewrite_at(&self, expr: &mut PrettyExpr<T>, path: &[usize]) -> bool {
+        let x = match expr.get_mut(path) {
+            Some(x) => x,
+            None => return false,
+        };
+        match self.matches(x) {
+            Some(bindings) => {
+                *x = instantiate(&self.template, &bindings);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn rewrite_paths(&self, expr: &mut PrettyExpr<T>, paths: &[Vec<usize>]) -> usize {
+        paths
+            .iter()
+            .rev()
+            .filter(|path| self.rewrite_at(expr, path))
+            .count()
+    }
+}
+
+fn read_one<T>(src: &str) -> Result<PrettyExpr<T>, RuleError> {
+    let mut forms = read_all(src).map_err(|e| RuleError(e.to_string()))?;
+    forms.retain(|x| !x.is_comment());
+    match forms.len() {
+        1 => Ok(forms.pop().unwrap()),
+        _ => Err(RuleError("expected exactly one expression".to_string())),
+    }
+}
+
+fn variable<T>(x: &PrettyExpr<T>) -> Option<(&str, bool)> {
+    if !x.is_atom() || x.is_comment() {
+        return None;
+    }
+    let name = x.get_text()?.strip_prefix('?')?;
+    match name.strip_suffix("...") {
+        Some(name) if !name.is_empty() => Some((name, true)),
+        _ if !name.is_empty() => Some((name, false)),
+        _ => None,
+    }
+}
+
+fn is_wildcard<T>(x: &PrettyExpr<T>) -> bool {
+    x.is_atom() && x.get_text() == Some("...")
+}
+
+fn variables<T>(x: &PrettyExpr<T>) -> Vec<String> {
+    let mut names = vec![];
+    x.walk(&mut |_, x| {
+        if let Some((name, _)) = variable(x) {
+            names.push(name.to_string());
+        }
+        true
+    });
+    names
+}
+
+fn bind<T: Clone>(bindings: &mut Bindings<T>, name: &str, value: Binding<T>) -> bool {
+    match (bindings.get(name), &value) {
+        (None, _) => {
+            bindings.insert(name.to_string(), value);
+            true
+        }
+        (Some(Binding::Node(a)), Binding::Node(b)) => same(a, b),
+        (Some(Binding::Seq(a)), Binding::Seq(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same(a, b))
+        }
+        (Some(_), _) => false,
+    }
+}
+
+fn same<T: Clone>(a: &PrettyExpr<T>, b: &PrettyExpr<T>) -> bool {
+    a.to_string() == b.to_string()
+}
+
+fn match_expr<T: Clone>(p: &PrettyExpr<T>, x: &PrettyExpr<T>, bindings: &mut Bindings<T>) -> bool {
+    use PrettyExpr::*;
+    if let Some((name, _)) = variable(p) {
+        return bind(bindings, name, Binding::Node(x.clone()));
+    }
+    match (p, x) {
+        (Style(_, p) | Folded(p), _) => match_expr(p, x, bindings),
+        (_, Style(_, x) | Folded(x)) => match_expr(p, x, bindings),
+        (Atom(_) | Stat(_), Atom(_) | Stat(_)) => p.get_text() == x.get_text(),
+        (Comment(a), Comment(b)) => a == b,
+        (Hole, Hole) => true,
+        (Quote(q, p), Quote(r, x)) => q == r && match_expr(p, x, bindings),
+        (Dotted(p), Dotted(x)) => match_expr(p, x, bindings),
+        (Inline(d, ps) | Expand(d, ps), Inline(e, xs) | Expand(e, xs)) => {
+            d == e && match_seq(ps, xs, bindings)
+        }
+        _ => false,
+    }
+}
+
+fn match_seq<T: Clone>(
+    ps: &[PrettyExpr<T>],
+    xs: &[PrettyExpr<T>],
+    bindings: &mut Bindings<T>,
+) -> bool {
+    let (p, ps) = match ps.split_first() {
+        Some(split) => split,
+        None => return xs.is_empty(),
+    };
+    let attempt = |bindings: &mut Bindings<T>, f: &dyn Fn(&mut Bindings<T>) -> bool| {
+        let mut candidate = bindings.clone();
+        let ok = f(&mut candidate);
+        if ok {
+            *bindings = candidate;
+        }
+        ok
+    };
+    if is_wildcard(p) {
+        return (0..=xs.len()).any(|n| attempt(bindings, &|b| match_seq(ps, &xs[n..], b)));
+    }
+    match variable(p) {
+        Some((name, true)) => (0..=xs.len()).any(|n| {
+            attempt(bindings, &|b| {
+                bind(b, name, Binding::Seq(xs[..n].to_vec())) && match_seq(ps, &xs[n..], b)
+            })
+        }),
+        _ => match xs.split_first() {
+            Some((x, xs)) => attempt(bindings, &|b| match_expr(p, x, b) && match_seq(ps, xs, b)),
+            None => false,
+        },
+    }
+}
+
+fn instantiate<T: Clone>(t: &PrettyExpr<T>, bindings: &Bindings<T>) -> PrettyExpr<T> {
+    use PrettyExpr::*;
+    if let Some((name, _)) = variable(t) {
+        return match &bindings[name] {
+            Binding::Node(x) => x.clone(),
+            Binding::Seq(xs) => PrettyExpr::list(xs.clone()),
+        };
+    }
+    match t {
+        Atom(_) | Stat(_) | Comment(_) | Hole => t.clone(),
+        Quote(q, x) => PrettyExpr::prefixed(*q, instantiate(x, bindings)),
+        Dotted(x) => PrettyExpr::dotted(instantiate(x, bindings)),
+        Inline(d, ts) | Expand(d, ts) => PrettyExpr::bracketed(*d, instantiate_seq(ts, bindings)),
+        Style(s, x) => PrettyExpr::styled(s.clone(), instantiate(x, bindings)),
+        Folded(x) => instantiate(x, bindings),
+    }
+}
+
+fn instantiate_seq<T: Clone>(ts: &[PrettyExpr<T>], bindings: &Bindings<T>) -> Vec<PrettyExpr<T>> {
+    ts.iter()
+        .flat_map(|t| match variable(t) {
+            Some((name, true)) => match &bindings[name] {
+                Binding::Seq(xs) => xs.clone(),
+                Binding::Node(x) => vec![x.clone()],
+            },
+            _ => vec![instantiate(t, bindings)],
+        })
+        .collect()
+}
+
+#[test]
+fn tests() {
+    let rewrite = |pattern: &str, template: &str, src: &str| -> (usize, String) {
+        let rule = Rule::<()>::new(pattern, template).unwrap();
+        let mut doc = PrettyExpr::list(read_all(src).unwrap());
+        let paths = rule.find_matches(&doc);
+        let n = rule.rewrite_paths(&mut doc, &paths);
+        let pf = crate::sxfmt::PrettyFormatter::new(80, 2);
+        (n, pf.pretty(doc.elements().unwrap()[0].clone()).to_string())
+    };
+
+    assert_eq!(
+        rewrite(
+            "(if ?c ?a #f)",
+            "(and ?c ?a)",
+            "(f (if x y #f) (if (g z) 1 #f) (if a b c))"
+        ),
+        (2, "(f (and x y) (and (g z) 1) (if a b c))".to_string())
+    );
+    assert_eq!(
+        rewrite(
+            "(list ?x ?rest...)",
+            "(cons ?x (list ?rest...))",
+            "(list 1 2 3)"
+        ),
+        (1, "(cons 1 (list 2 3))".to_string())
+    );
+    assert_eq!(
+        rewrite("(+ ?x ?x)", "(* 2 ?x)", "[(+ a a) (+ a b) (+ (f 1) (f 1))]"),
+        (2, "[(* 2 a) (+ a b) (* 2 (f 1))]".to_string())
+    );
+    assert_eq!(
+        rewrite(
+            "(let (?bs...) ?body)",
+            "'?body",
+            "(let ((a 1) (b 2)) (+ a b))"
+        ),
+        (1, "'(+ a b)".to_string())
+    );
+
+    let pattern = TreePattern::<()>::new("(define (?name ...) ...)").unwrap();
+    let doc = PrettyExpr::list(
+        read_all("(define (f x) x) (define y 1) (define (g) (define (h) 2))").unwrap(),
+    );
+    assert_eq!(
+        pattern.find_matches(&doc),
+        vec![vec![0], vec![2], vec![2, 2]]
+    );
+
+    assert_eq!(
+        Rule::<()>::new("(f ?x)", "(g ?y)").err().unwrap().0,
+        "unbound variable ?y"
+    );
+    assert!(Rule::<()>::new("(f ?x", "?x").is_err());
+    assert!(Rule::<()>::new("a b", "a").is_err());
+}
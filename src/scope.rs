@@ -0,0 +1,535 @@
+use crate::sxfmt::PrettyExpr;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Binding {
+    Bound(Vec<usize>),
+    Free(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BindingForm {
+    Let,
+    LetStar,
+    Letrec,
+    Lambda,
+    Define,
+    Do,
+}
+
+#[derive(Debug, Clone)]
+pub struct BindingForms(HashMap<String, BindingForm>);
+
+impl Default for BindingForms {
+    fn default() -> Self {
+        let mut forms = BindingForms(HashMap::new());
+        forms.insert("let", BindingForm::Let);
+        forms.insert("let*", BindingForm::LetStar);
+        forms.insert("letrec", BindingForm::Letrec);
+        forms.insert("letrec*", BindingForm::Letrec);
+        forms.insert("lambda", BindingForm::Lambda);
+        forms.insert("define", BindingForm::Define);
+        forms.insert("do", BindingForm::Do);
+        forms
+    }
+}
+
+impl BindingForms {
+    pub fn insert(&mut self, head: &str, form: BindingForm) {
+        self.0.insert(head.to_string(), form);
+    }
+
+    pub fn remove(&mut self, head: &str) {
+        self.0.remove(head);
+    }
+
+    fn get<T>(&self, head: &PrettyExpr<T>) -> Option<BindingForm> {
+        if !is_symbol(head) {
+            return None;
+        }
+        self.0.get(head.get_text()?).copied()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    resolved: Vec<(Vec<usize>, Binding)>,
+}
+
+impl Analysis {
+    pub fn new<T>(root: &PrettyExpr<T>, forms: &BindingForms) -> Self {
+        let mut resolver = Resolver::new(forms, None);
+        resolver.body(root.elements().unwrap_or_default(), 0, &mut vec![]);
+        let mut resolved = resolver.found;
+        resolved.sort_by(|a, b| a.0.cmp(&b.0));
+        Analysis { resolved }
+    }
+
+    pub fn binding(&self, path: &[usize]) -> Option<&Binding> {
+        let i = self
+            .resolved
+            .binary_search_by(|(p, _)| p.as_slice().cmp(path))
+            .ok()?;
+        Some(&self.resolved[i].1)
+    }
+
+    pub fn definition(&self, path: &[usize]) -> Option<&[usize]> {
+        match self.binding(path)? {
+            Binding::Bound(site) => Some(site),
+            Binding::Free(_) => None,
+        }
+    }
+
+    pub fn references(&self, path: &[usize]) -> Vec<Vec<usize>> {
+        let binding = match self.binding(path) {
+            Some(binding) => binding,
+            None => return vec![],
+        };
+        self.resolved
+            .iter()
+            .filter(|(_, b)| b == binding)
+            .map(|(p, _)| p.clone())
+            .collect()
+    }
+}
+
+pub fn rename_captures<T: Clone>(
+    root: &PrettyExpr<T>,
+    forms: &BindingForms,
+    references: &[Vec<usize>],
+    name: &str,
+) -> bool {
+    let mut renamed = root.clone();
+    for path in references {
+        renamed.get_mut(path).unwrap().set_text(name.to_string());
+    }
+    let before = Analysis::new(root, forms);
+    let after = Analysis::new(&renamed, forms);
+    before.resolved.iter().any(|(path, binding)| {
+        let expected = match binding {
+            Binding::Free(_) if references.contains(path) => Binding::Free(name.to_string()),
+            _ => binding.clone(),
+        };
+        after.binding(path) != Some(&expected)
+    })
+}
+
+pub fn names_in_scope<T>(
+    root: &PrettyExpr<T>,
+    forms: &BindingForms,
+    path: &[usize],
+) -> Vec<String> {
+    let mut resolver = Resolver::new(forms, Some(path));
+    resolver.body(root.elements().unwrap_or_default(), 0, &mut vec![]);
+    resolver.visible.unwrap_or_default()
+}
+
+pub fn is_symbol<T>(x: &PrettyExpr<T>) -> bool {
+    if !x.is_atom() || x.is_comment() {
+        return false;
+    }
+    let mut chars = x.get_text().unwrap_or_default().chars();
+    !matches!(
+        (chars.next(), chars.next()),
+        (None, _)
+            | (Some('"' | '#' | ':' | '0'..='9'), _)
+            | (Some('+' | '-' | '.'), Some('0'..='9'))
+    )
+}
+
+struct Resolver<'a> {
+    forms: &'a BindingForms,
+    env: Vec<(String, Vec<usize>)>,
+    found: Vec<(Vec<usize>, Binding)>,
+    target: Option<&'a [usize]>,
+    visible: Option<Vec<String>>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(forms: &'a BindingForms, target: Option<&'a [usize]>) -> Self {
+        Resolver {
+            forms,
+            env: vec![],
+            found: vec![],
+            target,
+            visible: None,
+        }
+    }
+
+    fn capture_scope(&mut self, path: &[usize]) {
+        if self.target != Some(path) || self.visible.is_some() {
+            return;
+        }
+        let mut names: Vec<String> = vec![];
+        for (name, _) in self.env.iter().rev() {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+        self.visible = Some(names);
+    }
+
+    fn reference<T>(&mut self, x: &PrettyExpr<T>, path: &[usize]) {
+        self.capture_scope(path);
+        if !is_symbol(x) {
+            return;
+        }
+        let name = x.get_text().unwrap();
+        let binding = match self.env.iter().rev().find(|(n, _)| n == name) {
+            Some((_, site)) => Binding::Bound(site.clone()),
+            None => Binding::Free(name.to_string()),
+        };
+        self.found.push((path.to_vec(), binding));
+    }
+
+    fn bind<T>(&mut self, x: &PrettyExpr<T>, path: &[usize]) {
+        self.capture_scope(path);
+        if is_symbol(x) {
+            let name = x.get_text().unwrap().to_string();
+            self.env.push((name, path.to_vec()));
+            self.found
+                .push((path.to_vec(), Binding::Bound(path.to_vec())));
+        }
+    }
+
+    fn expr<T>(&mut self, x: &PrettyExpr<T>, path: &mut Vec<usize>) {
+        if let Some(y) = x.quoted_value() {
+            path.push(0);
+            if x.prefix().unwrap().is_quoting() {
+                self.quoted(y, path);
+            } else {
+                self.expr(y, path);
+            }
+            path.pop();
+        } else if let Some(y) = x.tail_value() {
+            path.push(0);
+            self.expr(y, path);
+            path.pop();
+        } else if let Some(xs) = x.elements() {
+            if !self.binding_form(xs, path) {
+                self.exprs(xs, 0, path);
+            }
+        } else {
+            self.reference(x, path);
+        }
+    }
+
+    fn exprs<T>(&mut self, xs: &[PrettyExpr<T>], start: usize, path: &mut Vec<usize>) {
+        for (i, x) in xs.iter().enumerate().skip(start) {
+            path.push(i);
+            self.expr(x, path);
+            path.pop();
+        }
+    }
+
+    fn quoted<T>(&mut self, x: &PrettyExpr<T>, path: &mut Vec<usize>) {
+        if let Some(y) = x.quoted_value().or_else(|| x.tail_value()) {
+            path.push(0);
+            if x.prefix().map(|q| q.is_unquoting()) == Some(true) {
+                self.expr(y, path);
+            } else {
+                self.quoted(y, path);
+            }
+            path.pop();
+        } else if let Some(xs) = x.elements() {
+            for (i, x) in xs.iter().enumerate() {
+                path.push(i);
+                self.quoted(x, path);
+                path.pop();
+            }
+        }
+    }
+
+    fn body<T>(&mut self, xs: &[PrettyExpr<T>], start: usize, path: &mut Vec<usize>) {
+        let mark = self.env.len();
+        for (i, x) in xs.iter().enumerate().skip(start) {
+            if let Some((name, rest)) = self.definition(x) {
+                path.extend_from_slice(&[i, 1]);
+                path.extend_from_slice(rest);
+                self.env.push((name.to_string(), path.clone()));
+                path.truncate(path.len() - 2 - rest.len());
+            }
+        }
+        self.exprs(xs, start, path);
+        self.env.truncate(mark);
+    }
+
+    fn definition<'x, T>(&self, x: &'x PrettyExpr<T>) -> Option<(&'x str, &'static [usize])> {
+        match x.elements()? {
+            [head, name, ..] if self.forms.get(head) == Some(BindingForm::Define) => {
+                match name.elements() {
+                    Some([f, ..]) if is_symbol(f) => Some((f.get_text()?, &[0])),
+                    None if is_symbol(name) => Some((name.get_text()?, &[])),
+                    _ => None,
+                }
+            }
+            _ => None,
+        }
+    }
+
+    fn params<T>(&mut self, x: &PrettyExpr<T>, start: usize, path: &mut Vec<usize>) {
+        match x.elements() {
+            Some(xs) => {
+                for (i, x) in xs.iter().enumerate().skip(start) {
+                    path.push(i);
+                    match x.tail_value() {
+                        Some(y) => {
+                            path.push(0);
+                            self.bind(y, path);
+                            path.pop();
+                        }
+                        None => self.bind(x, path),
+                    }
+                    path.pop();
+                }
+            }
+            None => self.bind(x, path),
+        }
+    }
+
+    fn binding_form<T>(&mut self, xs: &[PrettyExpr<T>], path: &mut Vec<usize>) -> bool {
+        let form = match xs.first().and_then(|x| self.forms.get(x)) {
+            Some(form) if xs.len() >= 3 => form,
+            _ => return false,
+        };
+        let mark = self.env.len();
+        match form {
+            BindingForm::Lambda => {
+                path.push(1);
+                self.params(&xs[1], 0, path);
+                path.pop();
+                self.body(xs, 2, path);
+            }
+            BindingForm::Define if xs[1].elements().map(|ys| !ys.is_empty()) == Some(true) => {
+                let signature = &xs[1];
+                path.extend_from_slice(&[1, 0]);
+                self.reference(&signature.elements().unwrap()[0], path);
+                path.pop();
+                self.params(signature, 1, path);
+                path.pop();
+                self.body(xs, 2, path);
+            }
+            BindingForm::Define => {
+                path.push(1);
+                self.reference(&xs[1], path);
+                path.pop();
+                self.exprs(xs, 2, path);
+            }
+            BindingForm::Let | BindingForm::LetStar | BindingForm::Letrec => {
+                let named = form == BindingForm::Let && xs[1].elements().is_none();
+                let start = if named { 2 } else { 1 };
+                if !self.let_bindings(form, &xs[start], start, path) {
+                    return false;
+                }
+                if named {
+                    path.push(1);
+                    self.bind(&xs[1], path);
+                    path.pop();
+                }
+                self.body(xs, start + 1, path);
+            }
+            BindingForm::Do => {
+                if !self.do_bindings(&xs[1], path) {
+                    return false;
+                }
+                path.push(2);
+                match xs[2].elements() {
+                    Some(clause) => self.exprs(clause, 0, path),
+                    None => self.expr(&xs[2], path),
+                }
+                path.pop();
+                self.exprs(xs, 3, path);
+            }
+        }
+        self.env.truncate(mark);
+        true
+    }
+
+    fn let_bindings<T>(
+        &mut self,
+        form: BindingForm,
+        bindings: &PrettyExpr<T>,
+        start: usize,
+        path: &mut Vec<usize>,
+    ) -> bool {
+        let bindings = match bindings.elements() {
+            Some(bindings) => bindings,
+            None => return false,
+        };
+        path.push(start);
+        if form == BindingForm::Letrec {
+            for (i, binding) in bindings.iter().enumerate() {
+                if let Some([name, _]) = binding.elements() {
+                    self.bind(name, &[path.as_slice(), &[i, 0]].concat());
+                }
+            }
+        }
+        let mut sites = vec![];
+        for (i, binding) in bindings.iter().enumerate() {
+            path.push(i);
+            match binding.elements() {
+                Some([name, init]) => {
+                    path.push(1);
+                    self.expr(init, path);
+                    path.pop();
+                    path.push(0);
+                    match form {
+                        BindingForm::LetStar => self.bind(name, path),
+                        BindingForm::Letrec => {}
+                        _ => sites.push((name, path.clone())),
+                    }
+                    path.pop();
+                }
+                _ => self.expr(binding, path),
+            }
+            path.pop();
+        }
+        path.pop();
+        for (name, site) in sites {
+            self.bind(name, &site);
+        }
+        true
+    }
+
+    fn do_bindings<T>(&mut self, specs: &PrettyExpr<T>, path: &mut Vec<usize>) -> bool {
+        let specs = match specs.elements() {
+            Some(specs) => specs,
+            None => return false,
+        };
+        path.push(1);
+        for (i, spec) in specs.iter().enumerate() {
+            path.push(i);
+            match spec.elements() {
+                Some([_, init, ..]) => {
+                    path.push(1);
+                    self.expr(init, path);
+                    path.pop();
+                }
+                _ => self.expr(spec, path),
+            }
+            path.pop();
+        }
+        for (i, spec) in specs.iter().enumerate() {
+            if let Some([name, _, ..]) = spec.elements() {
+                self.bind(name, &[path.as_slice(), &[i, 0]].concat());
+            }
+        }
+        for (i, spec) in specs.iter().enumerate() {
+            if let Some([_, _, step]) = spec.elements() {
+                path.extend_from_slice(&[i, 2]);
+                self.expr(step, path);
+                path.truncate(path.len() - 2);
+            }
+        }
+        path.pop();
+        true
+    }
+}
+
+#[test]
+fn tests() {
+    use crate::reader::read_all;
+
+    let doc = PrettyExpr::<()>::list(
+        read_all(
+            "(define (f x) (let ((x (+ x 1)) (y x)) (* x y)))
+             (define y 2)
+             (lambda (a . b) (define (g) a) (g b y '(a ,y)))
+             (letrec ((ev (lambda (n) (od n))) (od (lambda (n) (ev n)))) (ev z))
+             (do ((i 0 (+ i 1))) ((= i y) i) (f i))",
+        )
+        .unwrap(),
+    );
+    let analysis = Analysis::new(&doc, &BindingForms::default());
+    let refs = |path: &[usize]| analysis.references(path);
+
+    assert_eq!(
+        refs(&[0, 1, 1]),
+        vec![vec![0, 1, 1], vec![0, 2, 1, 0, 1, 1], vec![0, 2, 1, 1, 1]]
+    );
+    assert_eq!(
+        refs(&[0, 2, 2, 1]),
+        vec![vec![0, 2, 1, 0, 0], vec![0, 2, 2, 1]]
+    );
+    assert_eq!(
+        refs(&[0, 2, 2, 2]),
+        vec![vec![0, 2, 1, 1, 0], vec![0, 2, 2, 2]]
+    );
+    assert_eq!(
+        refs(&[1, 1]),
+        vec![
+            vec![1, 1],
+            vec![2, 3, 2],
+            vec![2, 3, 3, 0, 1, 0],
+            vec![4, 2, 0, 2]
+        ]
+    );
+    assert_eq!(refs(&[2, 2, 1, 0]), vec![vec![2, 2, 1, 0], vec![2, 3, 0]]);
+    assert_eq!(refs(&[2, 1, 0]), vec![vec![2, 1, 0], vec![2, 2, 2]]);
+    assert_eq!(refs(&[2, 1, 1, 0]), vec![vec![2, 1, 1, 0], vec![2, 3, 1]]);
+    assert!(refs(&[2, 3, 3, 0, 0]).is_empty());
+
+    assert_eq!(
+        refs(&[3, 1, 0, 0]),
+        vec![vec![3, 1, 0, 0], vec![3, 1, 1, 1, 2, 0], vec![3, 2, 0]]
+    );
+    assert_eq!(
+        analysis.binding(&[3, 2, 1]),
+        Some(&Binding::Free("z".to_string()))
+    );
+
+    assert_eq!(
+        refs(&[4, 1, 0, 0]),
+        vec![
+            vec![4, 1, 0, 0],
+            vec![4, 1, 0, 2, 1],
+            vec![4, 2, 0, 1],
+            vec![4, 2, 1],
+            vec![4, 3, 1]
+        ]
+    );
+    assert_eq!(
+        analysis.binding(&[4, 3, 0]),
+        Some(&Binding::Bound(vec![0, 1, 0]))
+    );
+    assert_eq!(analysis.definition(&[2, 3, 0]), Some(&[2, 2, 1, 0][..]));
+    assert_eq!(analysis.definition(&[3, 2, 1]), None);
+
+    assert_eq!(
+        names_in_scope(&doc, &BindingForms::default(), &[0, 2, 2, 1]),
+        vec!["y", "x", "f"]
+    );
+    assert_eq!(
+        names_in_scope(&doc, &BindingForms::default(), &[2, 3, 1]),
+        vec!["g", "b", "a", "y", "f"]
+    );
+
+    let forms = BindingForms::default();
+    let captures = |src: &str, path: &[usize], name: &str| {
+        let doc = PrettyExpr::<()>::list(read_all(src).unwrap());
+        let references = Analysis::new(&doc, &forms).references(path);
+        rename_captures(&doc, &forms, &references, name)
+    };
+    assert!(captures("(lambda (x) (let ((y 1)) x))", &[0, 1, 0], "y"));
+    assert!(captures(
+        "(lambda (x) (let ((y 1)) x))",
+        &[0, 2, 1, 0, 0],
+        "x"
+    ));
+    assert!(captures(
+        "(define y 1) (lambda (x) (+ x y))",
+        &[1, 1, 0],
+        "y"
+    ));
+    assert!(captures("(define y 1) (car x)", &[1, 1], "y"));
+    assert!(!captures("(lambda (x) (let ((y 1)) x))", &[0, 1, 0], "z"));
+    assert!(!captures("(define y 1) (lambda (x) x) y", &[1, 1, 0], "y"));
+
+    let mut forms = BindingForms::default();
+    forms.remove("do");
+    let analysis = Analysis::new(&doc, &forms);
+    assert_eq!(
+        analysis.binding(&[4, 1, 0, 0]),
+        Some(&Binding::Free("i".to_string()))
+    );
+}
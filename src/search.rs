@@ -0,0 +1,128 @@
+use crate::prompt::Prompt;
+use crate::sxfmt::PrettyExpr;
+use regex::Regex;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SearchMode {
+    Substring,
+    Regex,
+}
+
+impl SearchMode {
+    fn label(&self) -> &'static str {
+        match self {
+            SearchMode::Substring => "search: ",
+            SearchMode::Regex => "regex search: ",
+        }
+    }
+}
+
+#[derive(Clone)]
+pub enum Pattern {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn new(query: &str, mode: SearchMode) -> Option<Self> {
+        if query.is_empty() {
+            return None;
+        }
+        match mode {
+            SearchMode::Substring => Some(Pattern::Substring(query.to_string())),
+            SearchMode::Regex => Regex::new(query).ok().map(Pattern::Regex),
+        }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            Pattern::Substring(s) => text.contains(s.as_str()),
+            Pattern::Regex(re) => re.is_match(text),
+        }
+    }
+
+    pub fn find_matches<T>(&self, expr: &PrettyExpr<T>) -> Vec<Vec<usize>> {
+        let mut paths = vec![];
+        expr.walk(&mut |path, x| {
+            if x.is_atom()
+                && !x.is_comment()
+                && x.get_text().map(|text| self.is_match(text)) == Some(true)
+            {
+                paths.push(path.to_vec());
+            }
+            true
+        });
+        paths
+    }
+}
+
+#[derive(Clone)]
+pub struct Search {
+    pub prompt: Prompt,
+    pub mode: SearchMode,
+    pub origin: Vec<usize>,
+}
+
+impl Search {
+    pub fn new(origin: Vec<usize>) -> Self {
+        let mode = SearchMode::Substring;
+        Search {
+            prompt: Prompt::new(mode.label()),
+            mode,
+            origin,
+        }
+    }
+
+    pub fn pattern(&self) -> Option<Pattern> {
+        Pattern::new(&self.prompt.text(), self.mode)
+    }
+
+    pub fn toggle_mode(&mut self) {
+        self.mode = match self.mode {
+            SearchMode::Substring => SearchMode::Regex,
+            SearchMode::Regex => SearchMode::Substring,
+        };
+        self.prompt.set_label(self.mode.label());
+    }
+}
+
+pub fn next_path<'a>(paths: &'a [Vec<usize>], from: &[usize], dir: i8) -> Option<&'a Vec<usize>> {
+    if dir > 0 {
+        paths
+            .iter()
+            .find(|path| path.as_slice() > from)
+            .or_else(|| paths.first())
+    } else {
+        paths
+            .iter()
+            .rev()
+            .find(|path| path.as_slice() < from)
+            .or_else(|| paths.last())
+    }
+}
+
+#[test]
+fn tests() {
+    use term_experiment::pe;
+
+    let p: PrettyExpr = pe![(define (foo x) (bar x "foo" foobar))];
+
+    let substring = Pattern::new("foo", SearchMode::Substring).unwrap();
+    let matches = substring.find_matches(&p);
+    assert_eq!(matches, vec![vec![1, 0], vec![2, 2], vec![2, 3]]);
+
+    let regex = Pattern::new("^(foo|x)$", SearchMode::Regex).unwrap();
+    let matches = regex.find_matches(&p);
+    assert_eq!(
+        matches,
+        vec![vec![1, 0], vec![1, 1], vec![2, 1], vec![2, 2]]
+    );
+
+    assert!(Pattern::new("(", SearchMode::Regex).is_none());
+    assert!(Pattern::new("", SearchMode::Substring).is_none());
+
+    assert_eq!(next_path(&matches, &[1, 1], 1), Some(&vec![2, 1]));
+    assert_eq!(next_path(&matches, &[2, 2], 1), Some(&vec![1, 0]));
+    assert_eq!(next_path(&matches, &[1, 0], -1), Some(&vec![2, 2]));
+    assert_eq!(next_path(&[], &[1, 0], -1), None);
+}
@@ -1,31 +1,278 @@
 use crate::backend::TextBuffer;
+use crate::commands::{self, Action, Command, Palette};
+use crate::completion::{complete, BUILTINS};
 use crate::events::Event;
-use crate::styles::Style;
-use crate::{EventHandler, Item, PrettyExpr, PrettyFormatter, TextBufferFormatter};
+use crate::highlight::highlight;
+use crate::prompt::Prompt;
+use crate::reader::read_all;
+use crate::rewrite::Rule;
+use crate::scope::{is_symbol, names_in_scope, rename_captures, Analysis, BindingForms};
+use crate::search::{next_path, Pattern, Search};
+use crate::snippet::{holes, Snippets};
+use crate::status_bar::{node_kind, Status};
+use crate::styles::{Style, RAINBOW};
+use crate::sxfmt::{format_path, parse_path, Delimiter, Prefix};
+use crate::{EventHandler, Formatter, Item, PrettyExpr, PrettyFormatter, TextBufferFormatter};
+use std::cell::OnceCell;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+const SAVE_WIDTH: usize = 80;
+const INDENT: usize = 2;
+const HISTORY_LIMIT: usize = 100;
+const POPUP_ITEMS: usize = 8;
+const MAX_COUNT: usize = 999;
+
+#[derive(Clone)]
+enum Replace {
+    Pattern(Prompt),
+    Template(String, Prompt),
+    Confirm(Rule<Style>, Vec<usize>),
+}
+
+#[derive(Clone)]
+struct Rename {
+    prompt: Prompt,
+    references: Vec<Vec<usize>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Mode {
+    Normal,
+    Insert,
+}
+
+impl Mode {
+    fn label(&self) -> &'static str {
+        match self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Completion {
+    items: Vec<String>,
+    selected: usize,
+}
 
 #[derive(Clone)]
 pub struct SexprView {
     expr: PrettyExpr<Style>,
+    file: Option<PathBuf>,
     width: usize,
     height: usize,
     cursor: Vec<usize>,
+    text_cursor: Option<usize>,
+    delimiter_palette: Vec<Style>,
+    skip_quoted_occurrences: bool,
+    search: Option<Search>,
+    last_pattern: Option<Pattern>,
+    replace: Option<Replace>,
+    rename: Option<Rename>,
+    binding_forms: BindingForms,
+    analysis: OnceCell<Analysis>,
+    history: Vec<Vec<usize>>,
+    builtins: Vec<String>,
+    completion: Option<Completion>,
+    snippets: Snippets,
+    snippet_root: Option<Vec<usize>>,
+    selection: Option<usize>,
+    clipboard: Vec<PrettyExpr<Style>>,
+    expansions: Vec<(Vec<usize>, Option<usize>)>,
+    mode: Option<Mode>,
+    count: Option<usize>,
+    palette: Option<Palette>,
+    command_history: Vec<String>,
+    modified: bool,
+    message: Option<String>,
 }
 
 impl SexprView {
-    pub fn new(expr: PrettyExpr<Style>, width: usize, height: usize) -> Self {
+    pub fn new(mut forms: Vec<PrettyExpr<Style>>, width: usize, height: usize) -> Self {
+        if forms.is_empty() {
+            forms.push(PrettyExpr::empty_list());
+        }
         SexprView {
-            expr,
+            expr: PrettyExpr::list(forms),
+            file: None,
             width,
             height,
-            cursor: vec![],
+            cursor: vec![0],
+            text_cursor: None,
+            delimiter_palette: RAINBOW.to_vec(),
+            skip_quoted_occurrences: true,
+            search: None,
+            last_pattern: None,
+            replace: None,
+            rename: None,
+            binding_forms: BindingForms::default(),
+            analysis: OnceCell::new(),
+            history: vec![],
+            builtins: BUILTINS.iter().map(|s| s.to_string()).collect(),
+            completion: None,
+            snippets: Snippets::default(),
+            snippet_root: None,
+            selection: None,
+            clipboard: vec![],
+            expansions: vec![],
+            mode: None,
+            count: None,
+            palette: None,
+            command_history: vec![],
+            modified: false,
+            message: None,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>, width: usize, height: usize) -> std::io::Result<Self> {
+        let path = path.as_ref();
+        let forms = if path.exists() {
+            let src = std::fs::read_to_string(path)?;
+            read_all(&src).map_err(|e| Error::new(ErrorKind::InvalidData, e))?
+        } else {
+            vec![]
+        };
+        let mut view = SexprView::new(forms, width, height);
+        view.file = Some(path.to_path_buf());
+        let folds = fold_file(path);
+        if folds.exists() {
+            for path in std::fs::read_to_string(folds)?
+                .lines()
+                .filter_map(parse_path)
+            {
+                if let Some(x) = view.expr.get_mut(&path).filter(|x| is_foldable(x)) {
+                    x.set_folded(true);
+                }
+            }
+        }
+        Ok(view)
+    }
+
+    fn document_text(&self) -> String {
+        let pf = PrettyFormatter::new(SAVE_WIDTH, INDENT);
+        let mut text = String::new();
+        for (i, form) in self.expr.elements().unwrap().iter().enumerate() {
+            if i > 0 {
+                text += form_separator(&self.expr, i);
+            }
+            let mut form = form.clone();
+            form.unfold_all();
+            text += &pf.pretty(form).to_string();
+        }
+        text + "\n"
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    fn changed(&mut self) {
+        self.analysis.take();
+        self.modified = true;
+    }
+
+    pub fn take_message(&mut self) -> Option<String> {
+        self.message.take()
+    }
+
+    pub fn status(&self) -> Status {
+        Status {
+            file: self.file.clone(),
+            modified: self.is_modified(),
+            mode: self.mode.map(|mode| mode.label()),
+            path: self.cursor.clone(),
+            kind: node_kind(self.expr.get(&self.cursor).unwrap()),
+            width: self.width,
+            indent: INDENT,
+        }
+    }
+
+    pub fn save(&mut self) -> std::io::Result<()> {
+        let path = match &self.file {
+            Some(path) => path,
+            None => return Err(Error::new(ErrorKind::NotFound, "no file name; use save-as")),
+        };
+        let text = self.document_text();
+        std::fs::write(path, text)?;
+        self.message = Some(format!("saved {}", path.display()));
+        self.modified = false;
+        let folds = self.folded_paths();
+        if !folds.is_empty() {
+            let text: Vec<_> = folds.iter().map(|path| format_path(path)).collect();
+            std::fs::write(fold_file(path), text.join("\n") + "\n")?;
+        } else if fold_file(path).exists() {
+            std::fs::remove_file(fold_file(path))?;
+        }
+        match holes(&self.expr).len() {
+            0 => {}
+            1 => self.message = Some("saved with 1 unfilled hole".to_string()),
+            n => self.message = Some(format!("saved with {} unfilled holes", n)),
+        }
+        Ok(())
+    }
+
+    pub fn set_file(&mut self, path: impl AsRef<Path>) {
+        self.file = Some(path.as_ref().to_path_buf());
+    }
+
+    pub fn save_as(&mut self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        self.set_file(path);
+        self.save()
+    }
+
+    pub fn set_delimiter_palette(&mut self, palette: Vec<Style>) {
+        self.delimiter_palette = palette;
+    }
+
+    pub fn reformat(&mut self) {
+        let mut paths = vec![];
+        self.expr.walk(&mut |path, x| {
+            if !path.is_empty() && x.is_expanded() {
+                paths.push(path.to_vec());
+            }
+            true
+        });
+        if !paths.is_empty() {
+            self.changed();
+        }
+        for path in paths {
+            self.expr.get_mut(&path).unwrap().set_expanded(false);
+        }
+    }
+
+    fn analysis(&self) -> &Analysis {
+        self.analysis
+            .get_or_init(|| Analysis::new(&self.expr, &self.binding_forms))
+    }
+
+    pub fn position(&self) -> (Vec<usize>, Option<usize>) {
+        (self.cursor.clone(), self.text_cursor)
+    }
+
+    pub fn is_valid_cursor(&self, path: &[usize]) -> bool {
+        !path.is_empty() && self.expr.is_valid_path(path)
+    }
+
+    pub fn set_cursor(&mut self, path: Vec<usize>) -> bool {
+        if !self.is_valid_cursor(&path) {
+            return false;
         }
+        self.text_cursor = None;
+        self.cursor = path;
+        true
     }
 
     pub fn move_cursor_out_of_list(&mut self) {
-        self.cursor.pop();
+        self.text_cursor = None;
+        if self.cursor.len() > 1 {
+            self.cursor.pop();
+        }
     }
 
     pub fn move_cursor_into_list(&mut self) {
+        self.text_cursor = None;
         self.cursor.push(0);
         if !self.expr.is_valid_path(&self.cursor) {
             self.cursor.pop().unwrap();
@@ -33,24 +280,118 @@ impl SexprView {
     }
 
     pub fn move_cursor_in_list(&mut self, dir: i8) {
+        self.text_cursor = None;
         if self.cursor.is_empty() {
             return;
         }
         let new_pos = self.cursor.pop().unwrap() as isize + dir as isize;
         let l = self.expr.get(&self.cursor).unwrap().len() as isize;
-        let new_pos = (new_pos + l) % l as isize;
+        let new_pos = (new_pos + l) % l;
         self.cursor.push(new_pos as usize);
     }
 
+    pub fn move_cursor_to_sibling(&mut self, dir: i8) {
+        self.text_cursor = None;
+        let (own, c_list) = self.cursor.split_last().unwrap();
+        let len = self.expr.get(c_list).unwrap().len() as isize;
+        let pos = (*own as isize + dir as isize).clamp(0, len - 1);
+        *self.cursor.last_mut().unwrap() = pos as usize;
+    }
+
+    pub fn move_cursor_to_end_of_list(&mut self, dir: i8) {
+        self.text_cursor = None;
+        let (_, c_list) = self.cursor.split_last().unwrap();
+        let len = self.expr.get(c_list).unwrap().len();
+        *self.cursor.last_mut().unwrap() = if dir < 0 { 0 } else { len - 1 };
+    }
+
+    pub fn move_cursor_to_last_child(&mut self) {
+        self.text_cursor = None;
+        let len = self.expr.get(&self.cursor).unwrap().len();
+        if len > 0 {
+            self.cursor.push(len - 1);
+        }
+    }
+
+    pub fn move_cursor_to_top_level(&mut self) {
+        self.text_cursor = None;
+        self.cursor.truncate(1);
+    }
+
+    fn preorder_paths(&self) -> Vec<Vec<usize>> {
+        let mut paths = vec![];
+        self.expr.walk(&mut |path, x| {
+            if !path.is_empty() {
+                paths.push(path.to_vec());
+            }
+            !x.is_folded()
+        });
+        paths
+    }
+
+    pub fn move_cursor_in_preorder(&mut self, dir: i8) {
+        if let Some(path) = next_path(&self.preorder_paths(), &self.cursor, dir) {
+            self.text_cursor = None;
+            self.cursor = path.clone();
+        }
+    }
+
+    pub fn move_cursor_to_list_at_depth(&mut self, dir: i8) {
+        let lists: Vec<_> = self
+            .preorder_paths()
+            .into_iter()
+            .filter(|path| path.len() == self.cursor.len())
+            .filter(|path| self.expr.get(path).unwrap().elements().is_some())
+            .collect();
+        if let Some(path) = next_path(&lists, &self.cursor, dir) {
+            self.text_cursor = None;
+            self.cursor = path.clone();
+        }
+    }
+
+    pub fn cycle_cursor(&mut self, dir: i8) {
+        if self.snippet_holes().is_empty() {
+            self.snippet_root = None;
+            self.move_cursor_in_preorder(dir);
+        } else {
+            self.move_cursor_to_hole(dir);
+        }
+    }
+
+    pub fn move_text_cursor(&mut self, dir: isize) {
+        if let Some(len) = self.cursor_text_len() {
+            let pos = self.text_cursor.unwrap_or(len) as isize + dir;
+            self.text_cursor = Some(pos.clamp(0, len as isize) as usize);
+        }
+    }
+
+    pub fn set_text_cursor(&mut self, pos: usize) {
+        if let Some(len) = self.cursor_text_len() {
+            self.text_cursor = Some(pos.min(len));
+        }
+    }
+
+    fn cursor_text_len(&self) -> Option<usize> {
+        self.expr
+            .get(&self.cursor)?
+            .get_text()
+            .map(|text| text.chars().count())
+    }
+
     pub fn append_at_cursor(&mut self, postfix: &str) {
         let x = self.expr.get_mut(&self.cursor).unwrap();
         if let Some(text) = x.get_text() {
-            let text = text.to_string() + postfix;
-            *x = PrettyExpr::Atom(text);
+            let pos = self.text_cursor.unwrap_or_else(|| text.chars().count());
+            let mut text = text.to_string();
+            text.insert_str(char_offset(&text, pos), postfix);
+            x.set_text(text);
+            self.text_cursor = Some(pos + postfix.chars().count());
+            self.changed();
         } else if x.is_empty_list() {
             x.elements_mut()
                 .unwrap()
                 .push(PrettyExpr::Atom(postfix.to_string()));
+            self.changed();
             self.move_cursor_into_list();
         }
     }
@@ -58,76 +399,1097 @@ impl SexprView {
     pub fn delete_at_cursor(&mut self) {
         let x = self.expr.get_mut(&self.cursor).unwrap();
         if let Some(text) = x.get_text() {
+            let pos = self.text_cursor.unwrap_or_else(|| text.chars().count());
+            if pos == 0 {
+                return;
+            }
             let mut text = text.to_string();
-            text.pop();
+            text.remove(char_offset(&text, pos - 1));
             if text.is_empty() {
                 *x = PrettyExpr::list(vec![]);
             } else {
-                *x = PrettyExpr::Atom(text);
+                x.set_text(text);
             }
+            self.text_cursor = Some(pos - 1);
+            self.changed();
+        }
+    }
+
+    pub fn delete_after_text_cursor(&mut self) {
+        let pos = match self.text_cursor {
+            Some(pos) => pos,
+            None => return,
+        };
+        let mut text = match self.expr.get(&self.cursor).unwrap().get_text() {
+            Some(text) if pos < text.chars().count() => text.to_string(),
+            _ => return,
+        };
+        self.changed();
+        text.remove(char_offset(&text, pos));
+        let x = self.expr.get_mut(&self.cursor).unwrap();
+        if text.is_empty() {
+            *x = PrettyExpr::list(vec![]);
+        } else {
+            x.set_text(text);
         }
     }
 
     pub fn delete_cursor_element(&mut self) {
-        match self.cursor.as_slice() {
-            [c_list @ .., c_elem] => {
-                let c_elem = *c_elem;
-                let x = self.expr.get_mut(c_list).unwrap();
-                x.remove_item(c_elem);
-                if x.is_empty_list() {
-                    self.cursor.pop();
-                } else {
-                    let last = self.cursor.last_mut().unwrap();
-                    *last = usize::min(c_elem, x.len() - 1)
+        self.text_cursor = None;
+        if let [c_list @ .., c_elem] = self.cursor.as_slice() {
+            let c_elem = *c_elem;
+            let x = self.expr.get_mut(c_list).unwrap();
+            let restored = match x.remove_item(c_elem) {
+                Some(y) => {
+                    y.is_empty_list()
+                        && (x.is_quotation()
+                            || x.is_tail()
+                            || (c_list.is_empty() && x.is_empty_list()))
                 }
+                None => true,
+            };
+            if let Some([tail]) = x.elements() {
+                if let Some(y) = tail.tail_value() {
+                    let y = y.clone();
+                    x.elements_mut().unwrap()[0] = y;
+                }
+            }
+            if c_list.is_empty() && x.is_empty_list() {
+                x.elements_mut().unwrap().push(PrettyExpr::empty_list());
+            } else if x.is_empty_list() {
+                self.cursor.pop();
+            } else {
+                let last = self.cursor.last_mut().unwrap();
+                *last = usize::min(c_elem, x.len() - 1)
+            }
+            if !restored {
+                self.changed();
             }
-            [] => {} //self.expr = PrettyExpr::empty_list(),
         }
     }
 
     pub fn insert_element_after_cursor(&mut self) {
-        match self.cursor.as_slice() {
-            [c_list @ .., c_elem] => {
-                let c_elem = *c_elem;
-                let x = self.expr.get_mut(c_list).unwrap();
-                if x.is_quotation() {
-                    self.move_cursor_out_of_list();
-                    self.insert_element_after_cursor();
-                } else {
-                    let elements = x.elements_mut().unwrap();
-                    elements.insert(c_elem + 1, PrettyExpr::empty_list());
-                    self.move_cursor_in_list(1);
+        self.text_cursor = None;
+        if let [c_list @ .., c_elem] = self.cursor.as_slice() {
+            let c_elem = *c_elem;
+            let x = self.expr.get_mut(c_list).unwrap();
+            if x.is_quotation() || x.is_tail() {
+                self.move_cursor_out_of_list();
+                self.insert_element_after_cursor();
+            } else if !x.elements().unwrap()[c_elem].is_tail() {
+                let elements = x.elements_mut().unwrap();
+                elements.insert(c_elem + 1, PrettyExpr::empty_list());
+                self.changed();
+                self.move_cursor_in_list(1);
+            }
+        }
+    }
+
+    pub fn quote_cursor(&mut self, prefix: Prefix) {
+        if self.expr.get(&self.cursor).unwrap().is_tail() {
+            return;
+        }
+        self.changed();
+        self.text_cursor = None;
+        let x = self.expr.get_mut(&self.cursor).unwrap();
+        let mut y = x.clone();
+        if prefix == Prefix::AnonymousFn && y.delimiter() != Some(Delimiter::List) {
+            y = PrettyExpr::list(vec![y]);
+        }
+        *x = PrettyExpr::prefixed(prefix, y);
+    }
+
+    fn enclosing_list_path(&self) -> Option<&[usize]> {
+        (1..self.cursor.len())
+            .rev()
+            .map(|n| &self.cursor[..n])
+            .find(|path| self.expr.get(path).unwrap().elements().is_some())
+    }
+
+    fn mark_enclosing_list(&self, i: usize, mut form: PrettyExpr<Style>) -> PrettyExpr<Style> {
+        let list = match self.enclosing_list_path() {
+            Some([c, list @ ..]) if *c == i => list,
+            _ => return form,
+        };
+        let own = self.cursor[list.len() + 1];
+        let mut path = list.to_vec();
+        for j in (0..form.get(list).unwrap().len()).filter(|&j| j != own) {
+            path.push(j);
+            form = form.with_style(&path, Style::Sibling).unwrap();
+            path.pop();
+        }
+        form.with_style(list, Style::Enclosing).unwrap()
+    }
+
+    pub fn occurrences(&self) -> Vec<Vec<usize>> {
+        let x = self.expr.get(&self.cursor).unwrap();
+        let text = match x.get_text() {
+            Some(text) if x.is_atom() && !x.is_comment() && !text.is_empty() => text,
+            _ => return vec![],
+        };
+        let mut paths = vec![];
+        self.expr.walk(&mut |path, x| {
+            if x.is_atom()
+                && !x.is_comment()
+                && x.get_text() == Some(text)
+                && !(self.skip_quoted_occurrences && self.is_quoted(path))
+            {
+                paths.push(path.to_vec());
+            }
+            true
+        });
+        paths
+    }
+
+    fn is_quoted(&self, path: &[usize]) -> bool {
+        let mut quoted = false;
+        for i in 0..path.len() {
+            match self.expr.get(&path[..i]).unwrap().prefix() {
+                Some(q) if !quoted && q.is_quoting() => quoted = true,
+                Some(q) if quoted && q.is_unquoting() => quoted = false,
+                _ => {}
+            }
+        }
+        quoted
+    }
+
+    pub fn move_cursor_to_occurrence(&mut self, dir: i8) {
+        let occurrences = self.occurrences();
+        if let Some(path) = next_path(&occurrences, &self.cursor, dir) {
+            self.jump_to(path.clone());
+        }
+    }
+
+    fn push_history(&mut self, path: Vec<usize>) {
+        if self.history.last() != Some(&path) {
+            self.history.push(path);
+        }
+        if self.history.len() > HISTORY_LIMIT {
+            self.history.remove(0);
+        }
+    }
+
+    pub fn jump_to(&mut self, path: Vec<usize>) {
+        if path != self.cursor {
+            self.push_history(self.cursor.clone());
+        }
+        self.text_cursor = None;
+        self.cursor = path;
+    }
+
+    fn snippet_holes(&self) -> Vec<Vec<usize>> {
+        let root = match &self.snippet_root {
+            Some(root) => root,
+            None => return vec![],
+        };
+        match self.expr.get(root) {
+            Some(x) => holes(x)
+                .into_iter()
+                .map(|hole| [root.as_slice(), &hole].concat())
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    pub fn move_cursor_to_hole(&mut self, dir: i8) {
+        if let Some(path) = next_path(&self.snippet_holes(), &self.cursor, dir) {
+            self.text_cursor = None;
+            self.cursor = path.clone();
+        }
+    }
+
+    pub fn expand_snippet_at_cursor(&mut self) {
+        let x = self.expr.get(&self.cursor).unwrap();
+        let snippet = match x.get_text() {
+            Some(name) if is_symbol(x) => self.snippets.expand(name),
+            _ => None,
+        };
+        if let Some(snippet) = snippet {
+            self.changed();
+            self.text_cursor = None;
+            if let Some(hole) = holes(&snippet).first() {
+                let path = [self.cursor.as_slice(), hole].concat();
+                *self.expr.get_mut(&self.cursor).unwrap() = snippet;
+                self.snippet_root = Some(self.cursor.clone());
+                self.cursor = path;
+            } else {
+                *self.expr.get_mut(&self.cursor).unwrap() = snippet;
+            }
+        }
+    }
+
+    pub fn goto_definition(&mut self) {
+        if let Some(site) = self.analysis().definition(&self.cursor) {
+            self.jump_to(site.to_vec());
+        }
+    }
+
+    pub fn navigate_back(&mut self) {
+        while let Some(path) = self.history.pop() {
+            if self.set_cursor(path) {
+                return;
+            }
+        }
+    }
+
+    pub fn toggle_quoted_occurrences(&mut self) {
+        self.skip_quoted_occurrences = !self.skip_quoted_occurrences;
+    }
+
+    pub fn start_search(&mut self) {
+        self.text_cursor = None;
+        self.search = Some(Search::new(self.cursor.clone()));
+    }
+
+    pub fn search_matches(&self) -> Vec<Vec<usize>> {
+        let pattern = match &self.search {
+            Some(search) => search.pattern(),
+            None => self.last_pattern.clone(),
+        };
+        pattern
+            .map(|pattern| pattern.find_matches(&self.expr))
+            .unwrap_or_default()
+    }
+
+    fn update_search(&mut self) {
+        let origin = match &self.search {
+            Some(search) => search.origin.clone(),
+            None => return,
+        };
+        let matches = self.search_matches();
+        self.cursor = matches
+            .iter()
+            .find(|path| **path >= origin)
+            .or_else(|| matches.first())
+            .cloned()
+            .unwrap_or(origin);
+    }
+
+    pub fn move_cursor_to_match(&mut self, dir: i8) {
+        let matches = self.search_matches();
+        if let Some(path) = next_path(&matches, &self.cursor, dir) {
+            self.text_cursor = None;
+            self.cursor = path.clone();
+        }
+    }
+
+    pub fn finish_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.last_pattern = search.pattern();
+            if search.origin != self.cursor {
+                self.push_history(search.origin);
+            }
+        }
+    }
+
+    pub fn cancel_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            self.cursor = search.origin;
+        }
+    }
+
+    fn handle_search_event(&mut self, event: &Event) -> bool {
+        use Event::*;
+        let search = self.search.as_mut().unwrap();
+        match event {
+            Confirm => self.finish_search(),
+            Cancel => self.cancel_search(),
+            NavDown | NavNextMatch => self.move_cursor_to_match(1),
+            NavUp | NavPrevMatch => self.move_cursor_to_match(-1),
+            Cycle => {
+                search.toggle_mode();
+                self.update_search();
+            }
+            _ if search.prompt.handle_event(event) => self.update_search(),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn start_replace(&mut self) {
+        self.text_cursor = None;
+        self.replace = Some(Replace::Pattern(Prompt::new("replace: ")));
+    }
+
+    fn next_replacement(
+        &mut self,
+        rule: Rule<Style>,
+        after: &[usize],
+        skip_subtree: bool,
+    ) -> Option<Replace> {
+        let path = rule
+            .find_matches(&self.expr)
+            .into_iter()
+            .find(|path| path.as_slice() > after && !(skip_subtree && path.starts_with(after)))?;
+        self.cursor = path.clone();
+        Some(Replace::Confirm(rule, path))
+    }
+
+    fn replacement_matches(&self) -> Vec<Vec<usize>> {
+        match &self.replace {
+            Some(Replace::Confirm(rule, _)) => rule.find_matches(&self.expr),
+            _ => vec![],
+        }
+    }
+
+    fn handle_replace_event(&mut self, event: &Event) -> bool {
+        self.replace = match (self.replace.take().unwrap(), event) {
+            (Replace::Pattern(_) | Replace::Template(..), Event::Cancel) => None,
+            (Replace::Pattern(prompt), Event::Confirm) => {
+                let pattern = prompt.text();
+                let label = format!("replace {} with: ", pattern);
+                Some(Replace::Template(pattern, Prompt::new(label)))
+            }
+            (Replace::Template(pattern, prompt), Event::Confirm) => {
+                match Rule::new(&pattern, &prompt.text()) {
+                    Ok(rule) => self.next_replacement(rule, &[], false),
+                    Err(e) => Some(Replace::Pattern(Prompt::new(format!("{}; replace: ", e)))),
+                }
+            }
+            (Replace::Pattern(mut prompt), _) => {
+                let handled = prompt.handle_event(event);
+                self.replace = Some(Replace::Pattern(prompt));
+                return handled;
+            }
+            (Replace::Template(pattern, mut prompt), _) => {
+                let handled = prompt.handle_event(event);
+                self.replace = Some(Replace::Template(pattern, prompt));
+                return handled;
+            }
+            (Replace::Confirm(rule, path), Event::Edit('y')) => {
+                self.changed();
+                rule.rewrite_at(&mut self.expr, &path);
+                self.next_replacement(rule, &path, true)
+            }
+            (Replace::Confirm(rule, path), Event::Edit('n')) => {
+                self.next_replacement(rule, &path, false)
+            }
+            (Replace::Confirm(rule, path), Event::Edit('!')) => {
+                let paths: Vec<_> = rule
+                    .find_matches(&self.expr)
+                    .into_iter()
+                    .filter(|p| *p == path || (*p > path && !p.starts_with(&path)))
+                    .collect();
+                self.changed();
+                rule.rewrite_paths(&mut self.expr, &paths);
+                None
+            }
+            (Replace::Confirm(..), Event::Edit('q') | Event::Cancel) => None,
+            (state @ Replace::Confirm(..), _) => Some(state),
+        };
+        true
+    }
+
+    pub fn start_rename(&mut self) {
+        let references = self.analysis().references(&self.cursor);
+        if references.is_empty() {
+            return;
+        }
+        self.text_cursor = None;
+        let name = self.expr.get(&self.cursor).unwrap().get_text().unwrap();
+        self.rename = Some(Rename {
+            prompt: Prompt::new(format!("rename {} to: ", name)),
+            references,
+        });
+    }
+
+    pub fn rename_references(&mut self, name: &str) -> Result<(), String> {
+        let references = match &self.rename {
+            Some(rename) => &rename.references,
+            None => return Err("nothing to rename".to_string()),
+        };
+        match read_all::<Style>(name).as_deref() {
+            Ok([x]) if is_symbol(x) => {}
+            _ => return Err(format!("invalid name {:?}", name)),
+        }
+        if rename_captures(&self.expr, &self.binding_forms, references, name) {
+            return Err(format!("{:?} would capture another binding", name));
+        }
+        self.changed();
+        for path in self.rename.take().unwrap().references {
+            self.expr.get_mut(&path).unwrap().set_text(name.to_string());
+        }
+        Ok(())
+    }
+
+    fn handle_rename_event(&mut self, event: &Event) -> bool {
+        let rename = self.rename.as_mut().unwrap();
+        match event {
+            Event::Confirm => {
+                let name = rename.prompt.text();
+                if let Err(e) = self.rename_references(&name) {
+                    let rename = self.rename.as_mut().unwrap();
+                    rename.prompt.set_label(format!("{}; rename to: ", e));
+                }
+            }
+            Event::Cancel => self.rename = None,
+            _ => return rename.prompt.handle_event(event),
+        }
+        true
+    }
+
+    pub fn start_palette(&mut self, text: &str) {
+        self.text_cursor = None;
+        self.palette = Some(Palette::new(text, self.command_history.clone()));
+    }
+
+    pub fn run_command(&mut self, command: &Command, argument: &str) {
+        if command.argument.is_some() && argument.is_empty() {
+            return self.start_palette(&format!("{} ", command.name));
+        }
+        match command.action {
+            Action::Event(event) => {
+                self.dispatch_event(&event);
+            }
+            Action::Run(run) => {
+                if let Err(e) = run(self, argument) {
+                    self.message = Some(e);
+                }
+            }
+        }
+    }
+
+    fn handle_palette_event(&mut self, event: &Event) -> bool {
+        let palette = self.palette.as_mut().unwrap();
+        match event {
+            Event::Confirm => {
+                let line = palette.command_line();
+                self.palette = None;
+                if line.trim().is_empty() {
+                    return true;
+                }
+                self.command_history.retain(|l| *l != line);
+                self.command_history.push(line.clone());
+                if self.command_history.len() > HISTORY_LIMIT {
+                    self.command_history.remove(0);
                 }
+                match commands::parse(&line) {
+                    Some((command, argument)) => self.run_command(command, argument),
+                    None => self.message = Some(format!("unknown command: {}", line.trim())),
+                }
+            }
+            Event::Cancel => self.palette = None,
+            _ => return palette.handle_event(event),
+        }
+        true
+    }
+
+    fn draw_palette(&self, buf: &mut TextBuffer, x: usize, y: usize) {
+        let palette = match &self.palette {
+            Some(palette) => palette,
+            None => return,
+        };
+        let first = (palette.selected + 1).saturating_sub(POPUP_ITEMS);
+        let items = &palette.items[first..palette.items.len().min(first + POPUP_ITEMS)];
+        let bottom = y + self.height.saturating_sub(1);
+        for (i, item) in items.iter().enumerate().take(bottom.saturating_sub(y)) {
+            let style = if first + i == palette.selected {
+                Style::PopupSelected
+            } else {
+                Style::Popup
+            };
+            let item = match commands::find(item).and_then(|c| c.argument) {
+                Some(argument) => format!("{} <{}>", item, argument),
+                None => item.clone(),
+            };
+            let line: String = format!(" {:width$}", item, width = self.width)
+                .chars()
+                .take(self.width)
+                .collect();
+            buf.draw_text(x, bottom - items.len().min(bottom - y) + i, &line, style);
+        }
+    }
+
+    fn highlighted_paths(&self) -> (Vec<Vec<usize>>, Style) {
+        if self.search.is_some() {
+            (self.search_matches(), Style::SearchMatch)
+        } else if self.replace.is_some() {
+            (self.replacement_matches(), Style::SearchMatch)
+        } else if let Some(rename) = &self.rename {
+            (rename.references.clone(), Style::SearchMatch)
+        } else {
+            (self.occurrences(), Style::Occurrence)
+        }
+    }
+
+    fn active_prompt(&self) -> Option<Prompt> {
+        if let Some(palette) = &self.palette {
+            return Some(palette.prompt.clone());
+        }
+        if let Some(search) = &self.search {
+            return Some(search.prompt.clone());
+        }
+        if let Some(rename) = &self.rename {
+            return Some(rename.prompt.clone());
+        }
+        match &self.replace {
+            Some(Replace::Pattern(prompt) | Replace::Template(_, prompt)) => Some(prompt.clone()),
+            Some(Replace::Confirm(..)) => Some(Prompt::new("replace? (y/n/!/q) ")),
+            None => None,
+        }
+    }
+
+    fn completion_candidates(&self) -> Vec<String> {
+        let mut candidates = names_in_scope(&self.expr, &self.binding_forms, &self.cursor);
+        self.expr.walk(&mut |path, x| {
+            if path != self.cursor && is_symbol(x) {
+                candidates.extend(x.get_text().map(str::to_string));
+            }
+            true
+        });
+        candidates.extend(self.builtins.iter().cloned());
+        candidates
+    }
+
+    fn update_completion(&mut self) {
+        self.completion = None;
+        let x = self.expr.get(&self.cursor).unwrap();
+        let text = match x.get_text() {
+            Some(text) if is_symbol(x) => text,
+            _ => return,
+        };
+        let pos = self.text_cursor.unwrap_or_else(|| text.chars().count());
+        let items = complete(
+            &text[..char_offset(text, pos)],
+            &self.completion_candidates(),
+        );
+        if !items.is_empty() {
+            self.completion = Some(Completion { items, selected: 0 });
+        }
+    }
+
+    fn accept_completion(&mut self) {
+        if let Some(completion) = self.completion.take() {
+            self.changed();
+            let text = completion.items[completion.selected].clone();
+            self.text_cursor = Some(text.chars().count());
+            self.expr.get_mut(&self.cursor).unwrap().set_text(text);
+        }
+    }
+
+    fn handle_completion_event(&mut self, event: &Event) -> bool {
+        let completion = self.completion.as_mut().unwrap();
+        let n = completion.items.len();
+        match event {
+            Event::NavDown => completion.selected = (completion.selected + 1) % n,
+            Event::NavUp => completion.selected = (completion.selected + n - 1) % n,
+            Event::Cycle | Event::Confirm => self.accept_completion(),
+            Event::Cancel => self.completion = None,
+            _ => {
+                self.completion = None;
+                return false;
             }
-            _ => {}
         }
+        true
     }
 
-    pub fn quote_cursor(&mut self) {
+    fn draw_completion(&self, buf: &mut TextBuffer, x: usize, y: usize, (cx, cy): (usize, usize)) {
+        let completion = match &self.completion {
+            Some(completion) => completion,
+            None => return,
+        };
+        let first = (completion.selected + 1).saturating_sub(POPUP_ITEMS);
+        let items = &completion.items[first..completion.items.len().min(first + POPUP_ITEMS)];
+        let width = items
+            .iter()
+            .map(|s| s.chars().count() + 2)
+            .max()
+            .unwrap_or(0);
+        let width = width.min(self.width);
+        let bottom = y + self.height.saturating_sub(1);
+        let top = if cy + 1 + items.len() <= bottom {
+            cy + 1
+        } else {
+            cy.saturating_sub(items.len()).max(y)
+        };
+        let left = cx.min(x + self.width - width);
+        for (i, item) in items.iter().enumerate().take(bottom.saturating_sub(top)) {
+            let style = if first + i == completion.selected {
+                Style::PopupSelected
+            } else {
+                Style::Popup
+            };
+            let line: String = format!(" {:width$}", item, width = width)
+                .chars()
+                .take(width)
+                .collect();
+            buf.draw_text(left, top + i, &line, style);
+        }
+    }
+
+    pub fn toggle_modal(&mut self) {
+        self.text_cursor = None;
+        self.count = None;
+        self.mode = match self.mode {
+            Some(_) => None,
+            None => Some(Mode::Normal),
+        };
+    }
+
+    fn handle_normal_key(&mut self, ch: char) -> bool {
+        let event = match ch {
+            '0'..='9' if ch != '0' || self.count.is_some() => {
+                let digit = ch as usize - '0' as usize;
+                self.count = Some((self.count.unwrap_or(0) * 10 + digit).min(MAX_COUNT));
+                return true;
+            }
+            'h' => Event::NavLeft,
+            'j' => Event::NavDown,
+            'k' => Event::NavUp,
+            'l' => Event::NavRight,
+            'd' => Event::Cut,
+            'y' => Event::Copy,
+            'p' => Event::Paste,
+            'w' => Event::EditWrap,
+            'u' => Event::EditUnwrap,
+            'i' | 'a' => {
+                self.count = None;
+                if self.cursor_text_len().is_none() {
+                    return true;
+                }
+                self.mode = Some(Mode::Insert);
+                self.set_text_cursor(if ch == 'i' { 0 } else { usize::MAX });
+                return true;
+            }
+            _ => {
+                self.count = None;
+                return true;
+            }
+        };
+        let count = self.count.take().unwrap_or(1);
+        match event {
+            Event::Cut | Event::Copy | Event::EditWrap => {
+                for _ in 1..count {
+                    self.dispatch_event(&Event::SelectDown);
+                }
+                self.dispatch_event(&event);
+            }
+            _ => {
+                for _ in 0..count {
+                    self.dispatch_event(&event);
+                }
+            }
+        }
+        true
+    }
+
+    fn splicing_prefix_at_cursor(&self) -> Option<Prefix> {
+        let (_, c_list) = self.cursor.split_last()?;
+        match self.expr.get(c_list)?.prefix()? {
+            Prefix::Unquote => Some(Prefix::UnquoteSplicing),
+            Prefix::Unsyntax => Some(Prefix::UnsyntaxSplicing),
+            _ => None,
+        }
+    }
+
+    pub fn can_dot_cursor(&self) -> bool {
+        match self.cursor.as_slice() {
+            [c_list @ .., c_elem] if *c_elem > 0 => {
+                let x = self.expr.get(c_list).unwrap();
+                x.elements().map(|xs| xs.len()) == Some(c_elem + 1)
+                    && x.get(&[*c_elem]).unwrap().is_empty_list()
+                    && !c_list.is_empty()
+            }
+            _ => false,
+        }
+    }
+
+    pub fn dot_cursor(&mut self) {
+        self.changed();
+        self.text_cursor = None;
         let x = self.expr.get_mut(&self.cursor).unwrap();
         let y = x.clone();
-        *x = PrettyExpr::quote(y);
+        *x = PrettyExpr::dotted(y);
     }
 
     pub fn wrap_cursor_in_list(&mut self) {
+        self.wrap_cursor_in(Delimiter::List)
+    }
+
+    fn is_anonymous_fn_body(&self, path: &[usize]) -> bool {
+        match path.split_last() {
+            Some((_, c_list)) => {
+                self.expr.get(c_list).unwrap().prefix() == Some(Prefix::AnonymousFn)
+            }
+            None => false,
+        }
+    }
+
+    pub fn wrap_cursor_in(&mut self, delimiter: Delimiter) {
+        if self.expr.get(&self.cursor).unwrap().is_tail()
+            || (delimiter != Delimiter::List && self.is_anonymous_fn_body(&self.cursor))
+        {
+            return;
+        }
+        self.changed();
+        self.text_cursor = None;
         let x = self.expr.get_mut(&self.cursor).unwrap();
         let y = x.clone();
-        *x = PrettyExpr::list(vec![y]);
+        *x = PrettyExpr::bracketed(delimiter, vec![y]);
     }
 
-    pub fn unwrap_unary_list_at_cursor(&mut self) {
+    pub fn set_delimiter_at_cursor(&mut self, delimiter: Delimiter) {
+        let x = self.expr.get(&self.cursor).unwrap();
+        let has_tail = x
+            .elements()
+            .and_then(|xs| xs.last())
+            .map(PrettyExpr::is_tail);
+        if delimiter != Delimiter::List
+            && (has_tail == Some(true) || self.is_anonymous_fn_body(&self.cursor))
+        {
+            return;
+        }
+        self.changed();
+        self.text_cursor = None;
+        self.expr
+            .get_mut(&self.cursor)
+            .unwrap()
+            .set_delimiter(delimiter);
+    }
+
+    fn folded_paths(&self) -> Vec<Vec<usize>> {
+        let mut paths = vec![];
+        self.expr.walk(&mut |path, x| {
+            if x.is_folded() {
+                paths.push(path.to_vec());
+            }
+            true
+        });
+        paths
+    }
+
+    pub fn toggle_fold_at_cursor(&mut self) {
         let x = self.expr.get_mut(&self.cursor).unwrap();
-        if let Some([y]) = x.elements() {
-            *x = y.clone();
-        } else if let Some(y) = x.quoted_value() {
-            *x = y.clone();
+        if x.is_folded() || is_foldable(x) {
+            self.text_cursor = None;
+            x.set_folded(!x.is_folded());
+        }
+    }
+
+    pub fn fold_to_depth(&mut self, depth: usize) {
+        self.expr.unfold_all();
+        let mut paths = vec![];
+        self.expr.walk(&mut |path, x| {
+            if path.len() == depth + 1 && is_foldable(x) {
+                paths.push(path.to_vec());
+            }
+            path.len() <= depth
+        });
+        for path in &paths {
+            self.expr.get_mut(path).unwrap().set_folded(true);
+        }
+        if let Some(n) =
+            (1..self.cursor.len()).find(|&n| paths.contains(&self.cursor[..n].to_vec()))
+        {
+            self.text_cursor = None;
+            self.cursor.truncate(n);
+        }
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.expr.unfold_all();
+    }
+
+    fn reveal_cursor(&mut self) {
+        for n in 1..self.cursor.len() {
+            if let Some(x) = self.expr.get_mut(&self.cursor[..n]) {
+                x.set_folded(false);
+            }
+        }
+    }
+
+    pub fn drag_cursor(&mut self, dir: i8) {
+        let (own, c_list) = self.cursor.split_last().unwrap();
+        let own = *own;
+        let xs = match self.expr.get_mut(c_list).unwrap().elements_mut() {
+            Some(xs) => xs,
+            None => return,
+        };
+        let other = own as isize + dir as isize;
+        if other < 0 || other as usize >= xs.len() {
+            return;
+        }
+        let other = other as usize;
+        if xs[own].is_tail() || xs[other].is_tail() {
+            return;
+        }
+        self.text_cursor = None;
+        xs.swap(own, other);
+        self.changed();
+        *self.cursor.last_mut().unwrap() = other;
+    }
+
+    pub fn promote_cursor(&mut self) {
+        let (own, c_list) = match self.cursor.split_last() {
+            Some((own, c_list)) if !c_list.is_empty() => (*own, c_list.to_vec()),
+            _ => return,
+        };
+        let mut c_holder = c_list.clone();
+        while let [c_outer @ .., _] = c_holder.as_slice() {
+            if !self.expr.get(c_outer).unwrap().is_quotation() {
+                break;
+            }
+            c_holder.pop();
+        }
+        let (parent, c_outer) = c_holder.split_last().unwrap();
+        let parent = *parent;
+        let leaves_tail = match self.expr.get(&c_list).unwrap().elements() {
+            Some([_, tail]) => tail.is_tail(),
+            Some(_) => false,
+            None => true,
+        };
+        let can_promote = self.expr.get(c_outer).unwrap().elements().is_some()
+            && !leaves_tail
+            && !self.expr.get(&self.cursor).unwrap().is_tail()
+            && !self.expr.get(&c_holder).unwrap().is_tail();
+        if !can_promote {
+            return;
+        }
+        self.changed();
+        self.text_cursor = None;
+        let x = self
+            .expr
+            .get_mut(&c_list)
+            .unwrap()
+            .remove_item(own)
+            .unwrap();
+        let xs = self.expr.get_mut(c_outer).unwrap().elements_mut().unwrap();
+        xs.insert(parent + 1, x);
+        self.cursor = [c_outer, &[parent + 1]].concat();
+    }
+
+    pub fn demote_cursor(&mut self) {
+        let (own, c_list) = self.cursor.split_last().unwrap();
+        let (own, c_list) = (*own, c_list.to_vec());
+        let xs = match self.expr.get(&c_list).unwrap().elements() {
+            Some(xs) if !xs[own].is_tail() => xs,
+            _ => return,
+        };
+        let is_list = |x: &PrettyExpr<Style>| x.elements().is_some();
+        let (target, at) = match (xs.get(own + 1), own.checked_sub(1).map(|i| &xs[i])) {
+            (Some(next), _) if is_list(next) => (own + 1, 0),
+            (_, Some(prev)) if is_list(prev) => {
+                let ys = prev.elements().unwrap();
+                let tail = ys.last().map(PrettyExpr::is_tail).unwrap_or(false);
+                (own - 1, ys.len() - tail as usize)
+            }
+            _ => return,
+        };
+        self.changed();
+        self.text_cursor = None;
+        let list = self.expr.get_mut(&c_list).unwrap();
+        let x = list.remove_item(own).unwrap();
+        let target = if target > own { target - 1 } else { target };
+        let ys = list.elements_mut().unwrap()[target].elements_mut().unwrap();
+        ys.insert(at, x);
+        self.cursor = [&c_list[..], &[target, at]].concat();
+    }
+
+    fn selection_range(&self) -> Option<(usize, usize)> {
+        let anchor = self.selection?;
+        let own = *self.cursor.last()?;
+        Some((anchor.min(own), anchor.max(own)))
+    }
+
+    fn selected_paths(&self) -> Vec<Vec<usize>> {
+        let (own, c_list) = self.cursor.split_last().unwrap();
+        let (lo, hi) = self.selection_range().unwrap_or((*own, *own));
+        (lo..=hi).map(|i| [c_list, &[i]].concat()).collect()
+    }
+
+    pub fn extend_selection(&mut self, dir: i8) {
+        self.text_cursor = None;
+        let (own, c_list) = self.cursor.split_last().unwrap();
+        let own = *own;
+        let len = match self.expr.get(c_list).unwrap().elements() {
+            Some(xs) => xs.len(),
+            None => return,
+        };
+        self.selection.get_or_insert(own);
+        let pos = (own as isize + dir as isize).clamp(0, len as isize - 1);
+        *self.cursor.last_mut().unwrap() = pos as usize;
+    }
+
+    pub fn expand_selection_to_parent(&mut self) {
+        self.text_cursor = None;
+        let (own, c_list) = self.cursor.split_last().unwrap();
+        let len = self.expr.get(c_list).unwrap().len();
+        let (lo, hi) = self.selection_range().unwrap_or((*own, *own));
+        if lo == 0 && hi + 1 >= len {
+            if !c_list.is_empty() {
+                self.selection = None;
+                self.cursor.pop();
+            }
+        } else if self.expr.get(c_list).unwrap().elements().is_some() {
+            self.selection = Some(0);
+            *self.cursor.last_mut().unwrap() = len - 1;
+        }
+    }
+
+    pub fn expand_region(&mut self) {
+        if self.cursor.len() < 2 {
+            return;
+        }
+        self.text_cursor = None;
+        self.expansions
+            .push((self.cursor.clone(), self.selection.take()));
+        self.cursor.pop();
+    }
+
+    pub fn shrink_region(&mut self) {
+        self.text_cursor = None;
+        match self.expansions.pop() {
+            Some((cursor, selection)) => {
+                self.cursor = cursor;
+                self.selection = selection;
+            }
+            None if self.selection.is_some() => self.selection = None,
+            None => self.move_cursor_into_list(),
+        }
+    }
+
+    pub fn wrap_selection_in(&mut self, delimiter: Delimiter) {
+        let (lo, hi) = match self.selection_range() {
+            Some(range) => range,
+            None => return self.wrap_cursor_in(delimiter),
+        };
+        if self
+            .selected_paths()
+            .iter()
+            .any(|path| self.expr.get(path).unwrap().is_tail())
+        {
+            return;
+        }
+        self.changed();
+        self.selection = None;
+        self.cursor.pop();
+        let xs = self
+            .expr
+            .get_mut(&self.cursor)
+            .unwrap()
+            .elements_mut()
+            .unwrap();
+        let ys = xs.drain(lo..=hi).collect();
+        xs.insert(lo, PrettyExpr::bracketed(delimiter, ys));
+        self.cursor.push(lo);
+    }
+
+    pub fn delete_selection(&mut self) {
+        let (lo, hi) = match self.selection_range() {
+            Some(range) => range,
+            None => return self.delete_cursor_element(),
+        };
+        self.selection = None;
+        *self.cursor.last_mut().unwrap() = lo;
+        for _ in lo..=hi {
+            self.delete_cursor_element();
+        }
+    }
+
+    pub fn copy_selection(&mut self) {
+        self.clipboard = self
+            .selected_paths()
+            .iter()
+            .map(|path| self.expr.get(path).unwrap().clone())
+            .collect();
+    }
+
+    pub fn cut_selection(&mut self) {
+        self.copy_selection();
+        self.delete_selection();
+    }
+
+    pub fn paste_after_cursor(&mut self) {
+        let (own, c_list) = self.cursor.split_last().unwrap();
+        let own = *own;
+        let xs = match self.expr.get_mut(c_list).unwrap().elements_mut() {
+            Some(xs) if !xs[own].is_tail() && !self.clipboard.is_empty() => xs,
+            _ => return,
+        };
+        self.text_cursor = None;
+        let at = if xs[own].is_empty_list() {
+            xs.remove(own);
+            own
+        } else {
+            own + 1
+        };
+        let n = self.clipboard.len();
+        let ys = self.clipboard.iter().map(|y| y.tail_value().unwrap_or(y));
+        xs.splice(at..at, ys.cloned());
+        self.changed();
+        *self.cursor.last_mut().unwrap() = at + n - 1;
+    }
+
+    pub fn quote_selection(&mut self, prefix: Prefix) {
+        if self.selection.is_none() {
+            return self.quote_cursor(prefix);
+        }
+        let own = self.cursor.clone();
+        for path in self.selected_paths() {
+            self.cursor = path;
+            self.quote_cursor(prefix);
+        }
+        self.cursor = own;
+        self.selection = None;
+    }
+
+    pub fn indent_selection(&mut self) {
+        self.changed();
+        self.text_cursor = None;
+        let paths = self.selected_paths();
+        let expanded = !paths
+            .iter()
+            .all(|path| self.expr.get(path).unwrap().is_expanded());
+        for path in paths {
+            self.expr.get_mut(&path).unwrap().set_expanded(expanded);
         }
     }
+
+    fn handle_selection_event(&mut self, event: &Event) -> bool {
+        match event {
+            Event::SelectUp => self.extend_selection(-1),
+            Event::SelectDown => self.extend_selection(1),
+            Event::SelectParent => self.expand_selection_to_parent(),
+            Event::ExpandRegion => self.expand_region(),
+            Event::ShrinkRegion => self.shrink_region(),
+            Event::EditWrap | Event::Edit('(') => self.wrap_selection_in(Delimiter::List),
+            Event::Edit('[') => self.wrap_selection_in(Delimiter::Vector),
+            Event::Edit('{') => self.wrap_selection_in(Delimiter::Map),
+            Event::EditDelete | Event::EditBackspace => self.delete_selection(),
+            Event::Cut => self.cut_selection(),
+            Event::Copy => {
+                self.copy_selection();
+                self.selection = None;
+            }
+            Event::Edit('\'') => self.quote_selection(Prefix::Quote),
+            Event::Edit('`') => self.quote_selection(Prefix::Quasiquote),
+            Event::Edit(',') => self.quote_selection(Prefix::Unquote),
+            Event::EditPrefix(prefix) => self.quote_selection(*prefix),
+            Event::EditIndent => self.indent_selection(),
+            Event::Cancel => self.selection = None,
+            _ => {
+                self.selection = None;
+                return false;
+            }
+        }
+        true
+    }
+
+    pub fn unwrap_unary_list_at_cursor(&mut self) {
+        let x = self.expr.get(&self.cursor).unwrap();
+        let y = match (x.elements(), x.quoted_value().or_else(|| x.tail_value())) {
+            (Some([y]), _) | (_, Some(y)) => y.clone(),
+            _ => return,
+        };
+        self.changed();
+        self.text_cursor = None;
+        *self.expr.get_mut(&self.cursor).unwrap() = y;
+    }
 }
 
 impl Item for SexprView {
     fn size(&self) -> (usize, usize) {
-        return (self.width, self.height);
+        (self.width, self.height)
     }
 
     fn resize(&mut self, width: usize, height: usize) {
@@ -136,46 +1498,723 @@ impl Item for SexprView {
     }
 
     fn draw(&self, buf: &mut TextBuffer, x: usize, y: usize) -> crossterm::Result<()> {
-        let mut pf = PrettyFormatter::default();
-        pf.max_code_width = self.width as usize;
-        let mut pe = pf.pretty(self.expr.clone());
+        let pf = PrettyFormatter::new(self.width, INDENT);
 
-        pe = pe
-            .with_style(&[], Style::Default)
-            .unwrap()
-            .with_style(&self.cursor, Style::Highlight)
-            .unwrap();
+        let cursor_style = if self.text_cursor.is_some() {
+            Style::Editing
+        } else {
+            Style::Highlight
+        };
 
-        let mut cf = TextBufferFormatter::new(buf, x, y);
-        pe.write(&mut cf)
+        let content_height = self.height.saturating_sub(1);
+        let (highlighted, highlight_style) = self.highlighted_paths();
+
+        let mut cf = TextBufferFormatter::new(buf, x, y, self.width, content_height)
+            .with_palette(&self.delimiter_palette);
+        for (i, form) in self.expr.elements().unwrap().iter().enumerate() {
+            if i > 0 {
+                for _ in form_separator(&self.expr, i).lines() {
+                    cf.write_newline()?;
+                }
+            }
+            let form = self.mark_enclosing_list(i, form.clone());
+            let mut pe = pf.pretty(highlight(form));
+            for path in &highlighted {
+                if let [c, rest @ ..] = path.as_slice() {
+                    if *c == i && *path != self.cursor {
+                        pe = pe.with_style(rest, highlight_style).unwrap();
+                    }
+                }
+            }
+            if self.selection.is_some() {
+                for path in self.selected_paths() {
+                    if let [c, rest @ ..] = path.as_slice() {
+                        if *c == i && path != self.cursor {
+                            pe = pe.with_style(rest, Style::Selection).unwrap();
+                        }
+                    }
+                }
+            }
+            if let [c, rest @ ..] = self.cursor.as_slice() {
+                if *c == i {
+                    pe = pe.with_style(rest, cursor_style).unwrap();
+                }
+            }
+            pe.write(&mut cf)?;
+        }
+
+        let cursor_position = cf.cursor_position;
+        if let (Some(pos), Some((cx, cy))) = (self.text_cursor, cursor_position) {
+            buf.set_cursor(Some((cx + pos, cy)));
+        }
+        if let Some(position) = cursor_position {
+            self.draw_completion(buf, x, y, position);
+        }
+        self.draw_palette(buf, x, y);
+
+        let depth = format!(" depth {} ", self.cursor.len() - 1);
+        if self.height > 0 && self.width >= depth.len() {
+            buf.draw_text(
+                x + self.width - depth.len(),
+                y + self.height - 1,
+                &depth,
+                Style::PathDepth,
+            );
+        }
+        let mode = match (self.mode, self.count) {
+            (Some(mode), Some(count)) => format!(" {} {} ", mode.label(), count),
+            (Some(mode), None) => format!(" {} ", mode.label()),
+            (None, _) => String::new(),
+        };
+        let status_width = depth.len() + mode.len();
+        if self.height > 0 && self.width >= status_width {
+            buf.draw_text(
+                x + self.width - status_width,
+                y + self.height - 1,
+                &mode,
+                Style::Mode,
+            );
+        }
+
+        if let (Some(prompt), true) = (self.active_prompt(), self.height > 0) {
+            let width = self.width.saturating_sub(status_width);
+            prompt.draw(buf, x, y + self.height - 1, width);
+        }
+        Ok(())
     }
 }
 
 impl EventHandler<Event> for SexprView {
     fn handle_event(&mut self, event: &Event) -> bool {
+        let handled = self.dispatch_event(event);
+        self.reveal_cursor();
+        handled
+    }
+}
+
+impl SexprView {
+    fn dispatch_event(&mut self, event: &Event) -> bool {
         use Event::*;
+        if !matches!(event, ExpandRegion | ShrinkRegion) {
+            self.expansions.clear();
+        }
+        if self.search.is_some() {
+            return self.handle_search_event(event);
+        }
+        if self.replace.is_some() {
+            return self.handle_replace_event(event);
+        }
+        if self.rename.is_some() {
+            return self.handle_rename_event(event);
+        }
+        if self.palette.is_some() {
+            return self.handle_palette_event(event);
+        }
+        if let (Some(Mode::Normal), Edit(ch)) = (self.mode, event) {
+            return self.handle_normal_key(*ch);
+        }
+        if self.completion.is_some() && self.handle_completion_event(event) {
+            return true;
+        }
+        if self.selection.is_some() && self.handle_selection_event(event) {
+            return true;
+        }
+        if let (Some(Mode::Insert), Cancel) = (self.mode, event) {
+            self.text_cursor = None;
+            self.mode = Some(Mode::Normal);
+            return true;
+        }
         match event {
+            ToggleModal => self.toggle_modal(),
+            Save => {
+                if let Err(e) = self.save() {
+                    self.message = Some(format!("could not save: {}", e));
+                }
+            }
+            Palette => self.start_palette(""),
+            Command(name) => match commands::find(name) {
+                Some(command) => self.run_command(command, ""),
+                None => return false,
+            },
+            Search => self.start_search(),
+            Replace => self.start_replace(),
+            Rename => self.start_rename(),
+            NavDefinition => self.goto_definition(),
+            Cycle => self.cycle_cursor(1),
+            CycleBack => self.cycle_cursor(-1),
+            EditSnippet => self.expand_snippet_at_cursor(),
+            EditIndent => self.indent_selection(),
+            EditDragUp => self.drag_cursor(-1),
+            EditDragDown => self.drag_cursor(1),
+            EditPromote => self.promote_cursor(),
+            EditDemote => self.demote_cursor(),
+            ToggleFold => self.toggle_fold_at_cursor(),
+            FoldToDepth(depth) => self.fold_to_depth(*depth),
+            UnfoldAll => self.unfold_all(),
+            SelectUp => self.extend_selection(-1),
+            SelectDown => self.extend_selection(1),
+            SelectParent => self.expand_selection_to_parent(),
+            ExpandRegion => self.expand_region(),
+            ShrinkRegion => self.shrink_region(),
+            Cut => self.cut_selection(),
+            Copy => self.copy_selection(),
+            Paste => self.paste_after_cursor(),
+            NavBack => self.navigate_back(),
+            NavNextMatch => self.move_cursor_to_match(1),
+            NavPrevMatch => self.move_cursor_to_match(-1),
+            NavLeft if self.text_cursor.is_some() => self.move_text_cursor(-1),
+            NavRight if self.text_cursor.is_some() => self.move_text_cursor(1),
             NavLeft => self.move_cursor_out_of_list(),
             NavRight => self.move_cursor_into_list(),
+            NavHome => self.set_text_cursor(0),
+            NavEnd => self.set_text_cursor(usize::MAX),
             NavDown => self.move_cursor_in_list(1),
             NavUp => self.move_cursor_in_list(-1),
+            NavNextSibling => self.move_cursor_to_sibling(1),
+            NavPrevSibling => self.move_cursor_to_sibling(-1),
+            NavFirstSibling => self.move_cursor_to_end_of_list(-1),
+            NavLastSibling => self.move_cursor_to_end_of_list(1),
+            NavLastChild => self.move_cursor_to_last_child(),
+            NavTopLevel => self.move_cursor_to_top_level(),
+            NavNextList => self.move_cursor_to_list_at_depth(1),
+            NavPrevList => self.move_cursor_to_list_at_depth(-1),
+            NavNextOccurrence => self.move_cursor_to_occurrence(1),
+            NavPrevOccurrence => self.move_cursor_to_occurrence(-1),
+            ToggleQuotedOccurrences => self.toggle_quoted_occurrences(),
             EditWrap => self.wrap_cursor_in_list(),
             EditUnwrap => self.unwrap_unary_list_at_cursor(),
+            EditDelete if self.text_cursor.is_some() => self.delete_after_text_cursor(),
             EditDelete => self.delete_cursor_element(),
             Edit('\'') => {
-                self.quote_cursor();
+                self.quote_cursor(Prefix::Quote);
+                self.move_cursor_into_list();
+            }
+            Edit('`') => {
+                self.quote_cursor(Prefix::Quasiquote);
+                self.move_cursor_into_list();
+            }
+            Edit(',') => {
+                self.quote_cursor(Prefix::Unquote);
+                self.move_cursor_into_list();
+            }
+            Edit('@') => match self.splicing_prefix_at_cursor() {
+                Some(prefix) => {
+                    let (_, c_list) = self.cursor.split_last().unwrap();
+                    self.expr.get_mut(c_list).unwrap().set_prefix(prefix);
+                    self.changed();
+                }
+                None => {
+                    self.quote_cursor(Prefix::Deref);
+                    self.move_cursor_into_list();
+                }
+            },
+            EditPrefix(prefix) => {
+                self.quote_cursor(*prefix);
+                self.move_cursor_into_list();
+            }
+            Edit('.') if self.can_dot_cursor() => {
+                self.dot_cursor();
                 self.move_cursor_into_list();
             }
             Edit('(') => {
-                self.wrap_cursor_in_list();
+                self.wrap_cursor_in(Delimiter::List);
+                self.move_cursor_into_list();
+            }
+            Edit('[') => {
+                self.wrap_cursor_in(Delimiter::Vector);
+                self.move_cursor_into_list();
+            }
+            Edit('{') => {
+                self.wrap_cursor_in(Delimiter::Map);
                 self.move_cursor_into_list();
             }
-            Edit(')') => self.move_cursor_out_of_list(),
+            Edit(')' | ']' | '}') => self.move_cursor_out_of_list(),
+            EditDelimiter(d) => self.set_delimiter_at_cursor(*d),
             Edit(' ') => self.insert_element_after_cursor(),
-            Edit(ch) => self.append_at_cursor(&ch.to_string()),
-            EditBackspace => self.delete_at_cursor(),
+            Edit(ch) => {
+                self.append_at_cursor(&ch.to_string());
+                self.update_completion();
+            }
+            EditBackspace => {
+                self.delete_at_cursor();
+                self.update_completion();
+            }
             _ => return false,
         }
         true
     }
 }
+
+fn is_foldable<T>(x: &PrettyExpr<T>) -> bool {
+    x.elements().map(|xs| !xs.is_empty()).unwrap_or(false)
+}
+
+fn fold_file(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".folds");
+    PathBuf::from(name)
+}
+
+fn form_separator<T>(document: &PrettyExpr<T>, i: usize) -> &'static str {
+    if document.get(&[i - 1]).unwrap().is_comment() {
+        "\n"
+    } else {
+        "\n\n"
+    }
+}
+
+fn char_offset(text: &str, pos: usize) -> usize {
+    text.char_indices()
+        .nth(pos)
+        .map(|(i, _)| i)
+        .unwrap_or(text.len())
+}
+
+#[test]
+fn text_cursor() {
+    let text = |view: &SexprView| {
+        let x = view.expr.get(&view.cursor).unwrap();
+        x.get_text().unwrap().to_string()
+    };
+    let mut view = SexprView::new(read_all("(abc λβγ)").unwrap(), 40, 10);
+    view.cursor = vec![0, 1];
+
+    for event in [Event::NavHome, Event::NavRight, Event::Edit('x')] {
+        view.handle_event(&event);
+    }
+    assert_eq!(text(&view), "λxβγ");
+    assert_eq!(view.text_cursor, Some(2));
+
+    view.handle_event(&Event::EditBackspace);
+    assert_eq!(text(&view), "λβγ");
+    assert_eq!(view.text_cursor, Some(1));
+
+    view.handle_event(&Event::EditDelete);
+    assert_eq!(text(&view), "λγ");
+    assert_eq!(view.text_cursor, Some(1));
+
+    view.handle_event(&Event::NavEnd);
+    view.handle_event(&Event::Edit('δ'));
+    assert_eq!(text(&view), "λγδ");
+
+    view.handle_event(&Event::NavHome);
+    view.handle_event(&Event::EditBackspace);
+    assert_eq!(text(&view), "λγδ");
+    assert_eq!(view.text_cursor, Some(0));
+
+    for _ in 0..3 {
+        view.handle_event(&Event::EditDelete);
+    }
+    assert!(view.expr.get(&[0, 1]).unwrap().is_empty_list());
+
+    assert_eq!(char_offset("aλb", 2), 3);
+    assert_eq!(char_offset("aλb", 9), 4);
+}
+
+#[test]
+fn save_round_trip() {
+    let src = ";; settings\n(def\n  config\n  {:name \"sx\"\n   :paths [\"src\" \"test\" \"bench\"]\n   :tags #{:edn :clj :cljs}\n   :nested {:a 1 :b [2 3]}})\n\n[x {y z}]\n";
+    let path = std::env::temp_dir().join(format!("sx-save-{}.edn", std::process::id()));
+    std::fs::write(&path, src).unwrap();
+    let mut view = SexprView::load(&path, 40, 10).unwrap();
+    view.save().unwrap();
+    let saved = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(saved, src);
+}
+
+#[test]
+fn tail_edits() {
+    let mut view = SexprView::new(read_all("(a . b)").unwrap(), 40, 10);
+    view.set_cursor(vec![0, 1]);
+    for event in [
+        Event::EditWrap,
+        Event::Edit('\''),
+        Event::EditPrefix(Prefix::Quasiquote),
+        Event::EditDelimiter(Delimiter::Vector),
+    ] {
+        view.set_cursor(vec![0, 1]);
+        view.handle_event(&event);
+    }
+    view.set_cursor(vec![0]);
+    view.handle_event(&Event::EditDelimiter(Delimiter::Vector));
+    view.set_cursor(vec![0, 0]);
+    view.handle_event(&Event::SelectDown);
+    view.handle_event(&Event::EditWrap);
+    assert_eq!(view.document_text(), "(a . b)\n");
+}
+
+#[test]
+fn anonymous_fn_body() {
+    let mut view = SexprView::new(read_all("#(+ % 1) [x]").unwrap(), 40, 10);
+    view.set_cursor(vec![0, 0]);
+    view.handle_event(&Event::EditDelimiter(Delimiter::Map));
+    view.handle_event(&Event::Edit('['));
+    view.set_cursor(vec![1]);
+    view.handle_event(&Event::EditPrefix(Prefix::AnonymousFn));
+    assert_eq!(view.document_text(), "#(+ % 1)\n\n#([x])\n");
+}
+
+#[test]
+fn quoted_occurrences() {
+    let mut view = SexprView::new(read_all("(f x '(x) `(a ,x ,@(g x) x))").unwrap(), 40, 10);
+    view.set_cursor(vec![0, 1]);
+    assert_eq!(
+        view.occurrences(),
+        vec![vec![0, 1], vec![0, 3, 0, 1, 0], vec![0, 3, 0, 2, 0, 1]]
+    );
+    view.toggle_quoted_occurrences();
+    assert_eq!(view.occurrences().len(), 5);
+}
+
+#[test]
+fn rename_capture() {
+    let mut view = SexprView::new(read_all("(lambda (x) (let ((y 1)) x))").unwrap(), 40, 10);
+    view.set_cursor(vec![0, 1, 0]);
+    view.handle_event(&Event::Rename);
+    assert!(view.rename_references("y").is_err());
+    assert!(view.rename_references("z").is_ok());
+    assert_eq!(view.document_text(), "(lambda (z) (let ((y 1)) z))\n");
+}
+
+#[test]
+fn selection_edits() {
+    let mut view = SexprView::new(read_all("(a b . c) (d e f)").unwrap(), 40, 10);
+    view.set_cursor(vec![0, 1]);
+    view.handle_event(&Event::SelectDown);
+    view.handle_event(&Event::Copy);
+    view.set_cursor(vec![1, 0]);
+    view.handle_event(&Event::Paste);
+    assert_eq!(view.document_text(), "(a b . c)\n\n(d b c e f)\n");
+    assert_eq!(view.cursor, vec![1, 2]);
+
+    view.handle_event(&Event::SelectDown);
+    view.handle_event(&Event::SelectDown);
+    view.handle_event(&Event::Edit('['));
+    assert_eq!(view.document_text(), "(a b . c)\n\n(d b [c e f])\n");
+
+    view.set_cursor(vec![1, 0]);
+    view.handle_event(&Event::SelectDown);
+    view.handle_event(&Event::Cut);
+    view.set_cursor(vec![0, 0]);
+    view.handle_event(&Event::Paste);
+    assert_eq!(view.document_text(), "(a d b b . c)\n\n([c e f])\n");
+}
+
+#[test]
+fn modified_and_messages() {
+    let mut view = SexprView::new(read_all("(a b) (c . d)").unwrap(), 40, 10);
+    view.set_cursor(vec![0, 1]);
+    view.set_text_cursor(1);
+    view.handle_event(&Event::EditDelete);
+    view.handle_event(&Event::EditUnwrap);
+    view.handle_event(&Event::NavLeft);
+    view.handle_event(&Event::EditBackspace);
+    assert_eq!(view.text_cursor, Some(0));
+    view.set_cursor(vec![1, 1, 0]);
+    view.handle_event(&Event::Edit(' '));
+    view.set_cursor(vec![0]);
+    view.handle_event(&Event::Edit('z'));
+    assert!(!view.is_modified());
+    assert_eq!(view.document_text(), "(a b)\n\n(c . d)\n");
+    view.set_cursor(vec![0, 1]);
+    view.set_text_cursor(0);
+    view.handle_event(&Event::Edit('c'));
+    assert!(view.is_modified());
+
+    view.handle_event(&Event::Save);
+    assert_eq!(
+        view.take_message().as_deref(),
+        Some("could not save: no file name; use save-as")
+    );
+    view.run_command(commands::find("goto").unwrap(), "9");
+    assert_eq!(view.take_message().as_deref(), Some("invalid path \"9\""));
+    assert_eq!(view.take_message(), None);
+}
+
+#[test]
+fn drag_and_promote() {
+    let mut view = SexprView::new(
+        read_all("(a b c . d) (x (a . b)) (f '(p q)) (k (m . n) z)").unwrap(),
+        40,
+        10,
+    );
+    view.set_cursor(vec![0, 0]);
+    view.handle_event(&Event::EditDragUp);
+    assert_eq!(view.cursor, vec![0, 0]);
+    view.handle_event(&Event::EditDragDown);
+    assert_eq!(view.cursor, vec![0, 1]);
+    view.set_cursor(vec![0, 2]);
+    view.handle_event(&Event::EditDragDown);
+    assert_eq!(view.cursor, vec![0, 2]);
+    view.set_cursor(vec![0, 3]);
+    view.handle_event(&Event::EditDragUp);
+    assert_eq!(view.cursor, vec![0, 3]);
+    view.set_cursor(vec![1, 1]);
+    view.handle_event(&Event::EditDragDown);
+    assert_eq!(view.cursor, vec![1, 1]);
+
+    view.set_cursor(vec![1, 1, 0]);
+    view.handle_event(&Event::EditPromote);
+    assert_eq!(view.cursor, vec![1, 1, 0]);
+
+    view.set_cursor(vec![2, 1, 0, 0]);
+    view.handle_event(&Event::EditPromote);
+    assert_eq!(view.cursor, vec![2, 2]);
+
+    view.set_cursor(vec![3, 2]);
+    view.handle_event(&Event::EditDemote);
+    assert_eq!(view.cursor, vec![3, 1, 1]);
+    assert_eq!(
+        view.document_text(),
+        "(b a c . d)\n\n(x (a . b))\n\n(f '(q) p)\n\n(k (m z . n))\n"
+    );
+}
+
+#[test]
+fn tree_navigation() {
+    let mut view = SexprView::new(read_all("(a (b c) . d) (e (f g) h)").unwrap(), 40, 10);
+    let nav = |view: &mut SexprView, event: Event| {
+        view.handle_event(&event);
+        view.cursor.clone()
+    };
+    view.set_cursor(vec![0, 0]);
+    assert_eq!(nav(&mut view, Event::NavPrevSibling), vec![0, 0]);
+    assert_eq!(nav(&mut view, Event::NavNextSibling), vec![0, 1]);
+    assert_eq!(nav(&mut view, Event::NavNextSibling), vec![0, 2]);
+    assert_eq!(nav(&mut view, Event::NavNextSibling), vec![0, 2]);
+    assert_eq!(nav(&mut view, Event::NavFirstSibling), vec![0, 0]);
+    assert_eq!(nav(&mut view, Event::NavLastSibling), vec![0, 2]);
+    assert_eq!(nav(&mut view, Event::NavLastChild), vec![0, 2, 0]);
+    assert_eq!(nav(&mut view, Event::NavLastChild), vec![0, 2, 0]);
+    assert_eq!(nav(&mut view, Event::NavTopLevel), vec![0]);
+
+    view.set_cursor(vec![0, 1, 1]);
+    assert_eq!(nav(&mut view, Event::Cycle), vec![0, 2]);
+    assert_eq!(nav(&mut view, Event::Cycle), vec![0, 2, 0]);
+    assert_eq!(nav(&mut view, Event::Cycle), vec![1]);
+    assert_eq!(nav(&mut view, Event::CycleBack), vec![0, 2, 0]);
+
+    view.set_cursor(vec![0, 1]);
+    assert_eq!(nav(&mut view, Event::NavNextList), vec![1, 1]);
+    assert_eq!(nav(&mut view, Event::NavPrevList), vec![0, 1]);
+
+    view.set_cursor(vec![1, 1]);
+    view.handle_event(&Event::ToggleFold);
+    view.set_cursor(vec![1, 0]);
+    assert_eq!(nav(&mut view, Event::Cycle), vec![1, 1]);
+    assert_eq!(nav(&mut view, Event::Cycle), vec![1, 2]);
+    assert_eq!(nav(&mut view, Event::CycleBack), vec![1, 1]);
+    assert_eq!(nav(&mut view, Event::CycleBack), vec![1, 0]);
+}
+
+#[test]
+fn snippet_holes() {
+    let mut view = SexprView::new(read_all("(f x) if").unwrap(), 40, 10);
+    view.set_cursor(vec![1]);
+    view.handle_event(&Event::EditSnippet);
+    assert_eq!(view.cursor, vec![1, 1]);
+    view.handle_event(&Event::Cycle);
+    assert_eq!(view.cursor, vec![1, 2]);
+    view.handle_event(&Event::CycleBack);
+    assert_eq!(view.cursor, vec![1, 1]);
+
+    for ch in ['1', '2', '3'] {
+        view.handle_event(&Event::Edit(ch));
+        view.handle_event(&Event::Cycle);
+    }
+    assert_eq!(view.document_text(), "(f x)\n\n(if 1 2 3)\n");
+    view.set_cursor(vec![0, 1]);
+    view.handle_event(&Event::Cycle);
+    assert_eq!(view.cursor, vec![1]);
+}
+
+#[test]
+fn expand_region() {
+    let mut view = SexprView::new(read_all("(let ((x 1)) (f x) y) (g)").unwrap(), 40, 10);
+    view.set_cursor(vec![0, 2, 1]);
+    view.handle_event(&Event::ExpandRegion);
+    assert_eq!(view.cursor, vec![0, 2]);
+    view.handle_event(&Event::ExpandRegion);
+    assert_eq!(view.cursor, vec![0]);
+    view.handle_event(&Event::ExpandRegion);
+    assert_eq!(view.cursor, vec![0]);
+    view.handle_event(&Event::ShrinkRegion);
+    assert_eq!(view.cursor, vec![0, 2]);
+    view.handle_event(&Event::ShrinkRegion);
+    assert_eq!(view.cursor, vec![0, 2, 1]);
+    view.handle_event(&Event::ShrinkRegion);
+    assert_eq!(view.cursor, vec![0, 2, 1]);
+
+    view.handle_event(&Event::ExpandRegion);
+    view.handle_event(&Event::NavPrevSibling);
+    view.handle_event(&Event::ShrinkRegion);
+    assert_eq!(view.cursor, vec![0, 1, 0]);
+
+    view.set_cursor(vec![0, 2, 0]);
+    view.handle_event(&Event::SelectDown);
+    view.handle_event(&Event::ExpandRegion);
+    assert_eq!((view.cursor.clone(), view.selection), (vec![0, 2], None));
+    view.handle_event(&Event::ShrinkRegion);
+    assert_eq!(
+        (view.cursor.clone(), view.selection),
+        (vec![0, 2, 1], Some(0))
+    );
+    view.handle_event(&Event::ShrinkRegion);
+    assert_eq!((view.cursor.clone(), view.selection), (vec![0, 2, 1], None));
+
+    view.handle_event(&Event::ExpandRegion);
+    view.handle_event(&Event::Cut);
+    assert_eq!(view.document_text(), "(let ((x 1)) y)\n\n(g)\n");
+    view.set_cursor(vec![0, 1, 0, 1]);
+    view.handle_event(&Event::ExpandRegion);
+    view.handle_event(&Event::EditWrap);
+    assert_eq!(view.document_text(), "(let (((x 1))) y)\n\n(g)\n");
+}
+
+#[test]
+fn normal_mode() {
+    let mut view = SexprView::new(read_all("(a b c d e) (f g)").unwrap(), 40, 10);
+    let keys = |view: &mut SexprView, keys: &str| {
+        for ch in keys.chars() {
+            view.handle_event(&Event::Edit(ch));
+        }
+    };
+    view.handle_event(&Event::ToggleModal);
+    assert_eq!(view.mode, Some(Mode::Normal));
+    view.set_cursor(vec![0, 0]);
+    keys(&mut view, "3j");
+    assert_eq!(view.cursor, vec![0, 3]);
+    keys(&mut view, "0k");
+    assert_eq!(view.cursor, vec![0, 2]);
+    keys(&mut view, "9999");
+    assert_eq!(view.count, Some(MAX_COUNT));
+    keys(&mut view, "x");
+    assert_eq!(view.count, None);
+
+    view.set_cursor(vec![0, 1]);
+    keys(&mut view, "2y");
+    assert_eq!(view.selection, None);
+    view.set_cursor(vec![0, 1]);
+    keys(&mut view, "2d");
+    assert_eq!(view.document_text(), "(a d e)\n\n(f g)\n");
+    keys(&mut view, "p");
+    assert_eq!(view.document_text(), "(a d b c e)\n\n(f g)\n");
+    view.set_cursor(vec![0, 0]);
+    keys(&mut view, "2w");
+    assert_eq!(view.document_text(), "((a d) b c e)\n\n(f g)\n");
+
+    keys(&mut view, "i");
+    assert_eq!((view.mode, view.text_cursor), (Some(Mode::Normal), None));
+    keys(&mut view, "la");
+    assert_eq!((view.mode, view.text_cursor), (Some(Mode::Insert), Some(1)));
+    keys(&mut view, "x");
+    view.handle_event(&Event::Cancel);
+    assert_eq!(view.mode, Some(Mode::Normal));
+    keys(&mut view, "i");
+    assert_eq!(view.text_cursor, Some(0));
+    assert_eq!(view.document_text(), "((ax d) b c e)\n\n(f g)\n");
+}
+
+#[test]
+fn enclosing_marks() {
+    struct Screen(Vec<(char, Style)>);
+
+    impl crate::RenderTarget for Screen {
+        type Error = ();
+        type Style = Style;
+
+        fn prepare(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn finalize(&mut self) -> Result<(), ()> {
+            Ok(())
+        }
+
+        fn write_char(&mut self, ch: char, s: &Style) -> Result<(), ()> {
+            self.0.push((ch, *s));
+            Ok(())
+        }
+
+        fn set_cursor(&mut self, _pos: Option<(usize, usize)>) -> Result<(), ()> {
+            Ok(())
+        }
+    }
+
+    let styled = |view: &SexprView, style: Style| {
+        let mut buf = TextBuffer::new(40, 5);
+        view.draw(&mut buf, 0, 0).unwrap();
+        let mut screen = Screen(vec![]);
+        buf.render(&mut screen).unwrap();
+        let text: String = screen
+            .0
+            .iter()
+            .map(|&(ch, s)| if s == style { ch } else { ' ' })
+            .collect();
+        text.chars()
+            .collect::<Vec<_>>()
+            .chunks(40)
+            .map(|row| row.iter().collect::<String>().trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+            .trim_end()
+            .to_string()
+    };
+
+    let mut view = SexprView::new(read_all("(a (b c d) e) (f g . (h i j))").unwrap(), 40, 5);
+    let cases = [
+        (vec![0, 1, 1], "   (     )", "    b   d", 2),
+        (vec![0, 0], "(           )", "   (b c d) e", 1),
+        (vec![1], "", "", 0),
+        (vec![1, 2], "\n\n(             )", "\n\n f g", 1),
+        (
+            vec![1, 2, 0, 1],
+            "\n\n       (     )",
+            "\n\n        h   j",
+            3,
+        ),
+    ];
+    for (cursor, delimiters, siblings, depth) in cases {
+        view.set_cursor(cursor);
+        assert_eq!(styled(&view, Style::MatchingDelimiter), delimiters);
+        assert_eq!(styled(&view, Style::Sibling), siblings);
+        let depth_label = styled(&view, Style::PathDepth);
+        assert!(depth_label.ends_with(&format!("\n{:>39}", format!("depth {}", depth))));
+    }
+}
+
+#[test]
+fn definition_history() {
+    let src = "(define (f x) x) (define y 1) (g (f y) y)";
+    let mut view = SexprView::new(read_all(src).unwrap(), 40, 10);
+    view.set_cursor(vec![2, 1, 0]);
+    view.handle_event(&Event::NavDefinition);
+    assert_eq!(view.cursor, vec![0, 1, 0]);
+    view.set_cursor(vec![2, 2]);
+    view.handle_event(&Event::NavDefinition);
+    assert_eq!(view.cursor, vec![1, 1]);
+    view.handle_event(&Event::NavBack);
+    assert_eq!(view.cursor, vec![2, 2]);
+    view.handle_event(&Event::NavBack);
+    assert_eq!(view.cursor, vec![2, 1, 0]);
+    view.handle_event(&Event::NavBack);
+    assert_eq!(view.cursor, vec![2, 1, 0]);
+
+    view.set_cursor(vec![0, 2]);
+    view.jump_to(vec![2, 1, 1]);
+    view.handle_event(&Event::NavDefinition);
+    assert_eq!(view.cursor, vec![1, 1]);
+    view.set_cursor(vec![2, 1]);
+    view.handle_event(&Event::Cut);
+    assert_eq!(view.document_text().lines().last(), Some("(g y)"));
+    view.handle_event(&Event::NavBack);
+    assert_eq!(view.cursor, vec![0, 2]);
+    assert!(view.history.is_empty());
+
+    for i in 0..HISTORY_LIMIT + 10 {
+        view.jump_to(vec![i % 2, 1]);
+        view.jump_to(vec![i % 2, 1]);
+    }
+    assert_eq!(view.history.len(), HISTORY_LIMIT);
+}
@@ -0,0 +1,94 @@
+use crate::reader::{read_all, ReadError};
+use crate::sxfmt::{PrettyExpr, HOLE};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+pub struct Snippets(HashMap<String, String>);
+
+impl Default for Snippets {
+    fn default() -> Self {
+        let mut snippets = Snippets(HashMap::new());
+        for (name, template) in [
+            ("let", "(let ((_ _)) _)"),
+            ("let*", "(let* ((_ _)) _)"),
+            ("letrec", "(letrec ((_ _)) _)"),
+            ("define", "(define (_ _) _)"),
+            ("lambda", "(lambda (_) _)"),
+            ("if", "(if _ _ _)"),
+            ("cond", "(cond (_ _) (else _))"),
+            ("when", "(when _ _)"),
+            ("do", "(do ((_ _ _)) (_ _) _)"),
+        ] {
+            snippets.insert(name, template).unwrap();
+        }
+        snippets
+    }
+}
+
+impl Snippets {
+    pub fn insert(&mut self, name: &str, template: &str) -> Result<(), ReadError> {
+        if read_all::<()>(template)?.len() != 1 {
+            return Err(ReadError {
+                line: 1,
+                message: "expected exactly one expression".to_string(),
+            });
+        }
+        self.0.insert(name.to_string(), template.to_string());
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.0.remove(name);
+    }
+
+    pub fn expand<T>(&self, name: &str) -> Option<PrettyExpr<T>> {
+        let mut forms = read_all(self.0.get(name)?).ok()?;
+        forms.pop().map(make_holes)
+    }
+}
+
+fn make_holes<T>(x: PrettyExpr<T>) -> PrettyExpr<T> {
+    match x {
+        PrettyExpr::Atom(text) if text == HOLE => PrettyExpr::Hole,
+        PrettyExpr::Atom(_) | PrettyExpr::Stat(_) | PrettyExpr::Comment(_) | PrettyExpr::Hole => x,
+        PrettyExpr::Quote(q, x) => PrettyExpr::prefixed(q, make_holes(*x)),
+        PrettyExpr::Dotted(x) => PrettyExpr::dotted(make_holes(*x)),
+        PrettyExpr::Inline(d, xs) => {
+            PrettyExpr::Inline(d, xs.into_iter().map(make_holes).collect())
+        }
+        PrettyExpr::Expand(d, xs) => {
+            PrettyExpr::Expand(d, xs.into_iter().map(make_holes).collect())
+        }
+        PrettyExpr::Style(s, x) => PrettyExpr::styled(s, make_holes(*x)),
+        PrettyExpr::Folded(x) => PrettyExpr::Folded(Box::new(make_holes(*x))),
+    }
+}
+
+pub fn holes<T>(expr: &PrettyExpr<T>) -> Vec<Vec<usize>> {
+    let mut paths = vec![];
+    expr.walk(&mut |path, x| {
+        if x.is_hole() {
+            paths.push(path.to_vec());
+        }
+        true
+    });
+    paths
+}
+
+#[test]
+fn tests() {
+    let mut snippets = Snippets::default();
+    let x: PrettyExpr = snippets.expand("let").unwrap();
+    assert_eq!(x.to_string(), "(let ((_ _)) _)");
+    assert_eq!(holes(&x), vec![vec![1, 0, 0], vec![1, 0, 1], vec![2]]);
+    assert_eq!(x.get(&[0]).unwrap().get_text(), Some("let"));
+
+    assert!(snippets.insert("bad", "(let").is_err());
+    assert!(snippets.insert("bad", "(a) (b)").is_err());
+    snippets.insert("set", "(set! _ '(_ . _))").unwrap();
+    let x: PrettyExpr = snippets.expand("set").unwrap();
+    assert_eq!(holes(&x), vec![vec![1], vec![2, 0, 0], vec![2, 0, 1, 0]]);
+
+    snippets.remove("let");
+    assert!(snippets.expand::<()>("let").is_none());
+}
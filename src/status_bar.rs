@@ -0,0 +1,183 @@
+use crate::backend::TextBuffer;
+use crate::styles::Style;
+use crate::sxfmt::{format_path, PrettyExpr};
+use crate::Item;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const MESSAGE_TIMEOUT: Duration = Duration::from_secs(4);
+const MESSAGE_FADE: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Default)]
+pub struct Status {
+    pub file: Option<PathBuf>,
+    pub modified: bool,
+    pub mode: Option<&'static str>,
+    pub path: Vec<usize>,
+    pub kind: String,
+    pub width: usize,
+    pub indent: usize,
+}
+
+pub struct StatusBar {
+    width: usize,
+    status: Status,
+    message: Option<(String, Instant)>,
+}
+
+impl StatusBar {
+    pub fn new(width: usize) -> Self {
+        StatusBar {
+            width,
+            status: Status::default(),
+            message: None,
+        }
+    }
+
+    pub fn set_status(&mut self, status: Status) {
+        self.status = status;
+    }
+
+    pub fn show_message(&mut self, message: impl Into<String>) {
+        self.message = Some((message.into(), Instant::now()));
+    }
+
+    fn message_age(&self) -> Option<Duration> {
+        let (_, shown) = self.message.as_ref()?;
+        Some(shown.elapsed()).filter(|age| *age < MESSAGE_TIMEOUT)
+    }
+
+    pub fn next_update(&self) -> Option<Duration> {
+        let age = self.message_age()?;
+        let fade = MESSAGE_TIMEOUT - MESSAGE_FADE;
+        Some(if age < fade {
+            fade - age
+        } else {
+            MESSAGE_TIMEOUT - age
+        })
+    }
+
+    pub fn expire_message(&mut self) {
+        if self.message_age().is_none() {
+            self.message = None;
+        }
+    }
+
+    fn left_text(&self) -> String {
+        let name = match &self.status.file {
+            Some(file) => file.display().to_string(),
+            None => "[scratch]".to_string(),
+        };
+        let modified = if self.status.modified { " [+]" } else { "" };
+        let mode = match self.status.mode {
+            Some(mode) => format!("{} │ ", mode),
+            None => String::new(),
+        };
+        format!(
+            " {}{} │ {}{} │ {} ",
+            name,
+            modified,
+            mode,
+            format_path(&self.status.path),
+            self.status.kind
+        )
+    }
+
+    fn message_style(&self) -> Style {
+        match self.message_age() {
+            Some(age) if age < MESSAGE_TIMEOUT - MESSAGE_FADE => Style::Message,
+            _ => Style::MessageFading,
+        }
+    }
+}
+
+impl Item for StatusBar {
+    fn size(&self) -> (usize, usize) {
+        (self.width, 1)
+    }
+
+    fn resize(&mut self, width: usize, _height: usize) {
+        self.width = width;
+    }
+
+    fn draw(&self, buf: &mut TextBuffer, x: usize, y: usize) -> crossterm::Result<()> {
+        let left = self.left_text();
+        let right = format!(
+            " width {} indent {} ",
+            self.status.width, self.status.indent
+        );
+        let line: String = left
+            .chars()
+            .chain(std::iter::repeat(' '))
+            .take(self.width)
+            .collect();
+        buf.draw_text(x, y, &line, Style::StatusBar);
+        let mut used = left.chars().count();
+        let mut right_width = right.chars().count();
+        if used + right_width <= self.width {
+            buf.draw_text(x + self.width - right_width, y, &right, Style::StatusBar);
+        } else {
+            right_width = 0;
+        }
+        if let Some((message, _)) = self
+            .message
+            .as_ref()
+            .filter(|_| self.message_age().is_some())
+        {
+            used = used.min(self.width);
+            let room = self.width.saturating_sub(used + right_width);
+            let text: String = format!(" {} ", message).chars().take(room).collect();
+            buf.draw_text(x + used, y, &text, self.message_style());
+        }
+        Ok(())
+    }
+}
+
+pub fn node_kind<T>(x: &PrettyExpr<T>) -> String {
+    match x {
+        PrettyExpr::Atom(text) => format!("atom({})", text.chars().count()),
+        PrettyExpr::Stat(text) => format!("atom({})", text.chars().count()),
+        PrettyExpr::Comment(text) => format!("comment({})", text.chars().count()),
+        PrettyExpr::Hole => "hole".to_string(),
+        PrettyExpr::Quote(prefix, x) => format!("{} {}", prefix.name(), node_kind(x)),
+        PrettyExpr::Dotted(x) => format!("tail {}", node_kind(x)),
+        PrettyExpr::Inline(d, xs) | PrettyExpr::Expand(d, xs) => {
+            format!("{}({})", d.name(), xs.len())
+        }
+        PrettyExpr::Style(_, x) => node_kind(x),
+        PrettyExpr::Folded(x) => format!("folded {}", node_kind(x)),
+    }
+}
+
+#[test]
+fn tests() {
+    let p: PrettyExpr = crate::reader::read_all("(define (f x) '(a λb) . [c])")
+        .unwrap()
+        .remove(0);
+    assert_eq!(node_kind(&p), "list(4)");
+    assert_eq!(node_kind(p.get(&[0]).unwrap()), "atom(6)");
+    assert_eq!(node_kind(p.get(&[2]).unwrap()), "quote list(2)");
+    assert_eq!(node_kind(p.get(&[2, 0, 1]).unwrap()), "atom(2)");
+    assert_eq!(node_kind(p.get(&[3]).unwrap()), "tail vector(1)");
+
+    let mut bar = StatusBar::new(40);
+    bar.set_status(Status {
+        modified: true,
+        path: vec![0, 2],
+        kind: "atom(3)".to_string(),
+        ..Status::default()
+    });
+    assert_eq!(bar.left_text(), " [scratch] [+] │ 0.2 │ atom(3) ");
+    bar.status.mode = Some("NORMAL");
+    assert_eq!(bar.left_text(), " [scratch] [+] │ NORMAL │ 0.2 │ atom(3) ");
+
+    assert_eq!(bar.next_update(), None);
+    bar.show_message("saved");
+    assert!(bar.next_update().unwrap() <= MESSAGE_TIMEOUT - MESSAGE_FADE);
+    assert_eq!(bar.message_style(), Style::Message);
+    bar.message.as_mut().unwrap().1 -= MESSAGE_TIMEOUT - MESSAGE_FADE;
+    assert_eq!(bar.message_style(), Style::MessageFading);
+    bar.message.as_mut().unwrap().1 -= MESSAGE_FADE;
+    bar.expire_message();
+    assert!(bar.message.is_none());
+}
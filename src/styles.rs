@@ -1,13 +1,46 @@
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub enum Style {
+    #[default]
     Default,
     Background,
     Frame,
     Highlight,
+    Editing,
+    SpecialForm,
+    Number,
+    StringLiteral,
+    Quoted,
+    Keyword,
+    Comment,
+    Hole,
+    FoldMarker,
+    Enclosing,
+    MatchingDelimiter,
+    Sibling,
+    PathDepth,
+    Mode,
+    Occurrence,
+    Selection,
+    SearchMatch,
+    Prompt,
+    Popup,
+    PopupSelected,
+    StatusBar,
+    Message,
+    MessageFading,
+    Rainbow1,
+    Rainbow2,
+    Rainbow3,
+    Rainbow4,
+    Rainbow5,
+    Rainbow6,
 }
 
-impl Default for Style {
-    fn default() -> Style {
-        Style::Default
-    }
-}
+pub const RAINBOW: [Style; 6] = [
+    Style::Rainbow1,
+    Style::Rainbow2,
+    Style::Rainbow3,
+    Style::Rainbow4,
+    Style::Rainbow5,
+    Style::Rainbow6,
+];
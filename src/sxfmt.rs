@@ -1,30 +1,147 @@
 use std::marker::PhantomData;
 
+#[macro_export]
 macro_rules! pe {
-    (($($xs:tt)*)) => {$crate::sxfmt::PrettyExpr::list(vec![$(pe!($xs)),*])};
+    (($($xs:tt)*)) => {$crate::sxfmt::PrettyExpr::list(vec![$($crate::pe!($xs)),*])};
+    ([$($xs:tt)*]) => {
+        $crate::sxfmt::PrettyExpr::bracketed($crate::sxfmt::Delimiter::Vector, vec![$($crate::pe!($xs)),*])
+    };
+    ({$($xs:tt)*}) => {
+        $crate::sxfmt::PrettyExpr::bracketed($crate::sxfmt::Delimiter::Map, vec![$($crate::pe!($xs)),*])
+    };
     ($x:ident) => {$crate::sxfmt::PrettyExpr::Stat(stringify!{$x})};
     ($x:expr) => {$crate::sxfmt::PrettyExpr::Atom($x.to_string())};
 }
 
+pub const HOLE: &str = "_";
+pub const FOLD_MARKER: &str = "…";
+const FOLD_ITEMS: usize = 2;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Delimiter {
+    List,
+    Vector,
+    Map,
+    Set,
+}
+
+impl Delimiter {
+    pub fn open(&self) -> &'static str {
+        match self {
+            Delimiter::List => "(",
+            Delimiter::Vector => "[",
+            Delimiter::Map => "{",
+            Delimiter::Set => "#{",
+        }
+    }
+
+    pub fn close(&self) -> &'static str {
+        match self {
+            Delimiter::List => ")",
+            Delimiter::Vector => "]",
+            Delimiter::Map | Delimiter::Set => "}",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Delimiter::List => "list",
+            Delimiter::Vector => "vector",
+            Delimiter::Map => "map",
+            Delimiter::Set => "set",
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Prefix {
+    Quote,
+    Quasiquote,
+    Unquote,
+    UnquoteSplicing,
+    Syntax,
+    Quasisyntax,
+    Unsyntax,
+    UnsyntaxSplicing,
+    Deref,
+    AnonymousFn,
+}
+
+impl Prefix {
+    pub fn text(&self) -> &'static str {
+        match self {
+            Prefix::Quote => "'",
+            Prefix::Quasiquote => "`",
+            Prefix::Unquote => ",",
+            Prefix::UnquoteSplicing => ",@",
+            Prefix::Syntax => "#'",
+            Prefix::Quasisyntax => "#`",
+            Prefix::Unsyntax => "#,",
+            Prefix::UnsyntaxSplicing => "#,@",
+            Prefix::Deref => "@",
+            Prefix::AnonymousFn => "#",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Prefix::Quote => "quote",
+            Prefix::Quasiquote => "quasiquote",
+            Prefix::Unquote => "unquote",
+            Prefix::UnquoteSplicing => "unquote-splicing",
+            Prefix::Syntax => "syntax",
+            Prefix::Quasisyntax => "quasisyntax",
+            Prefix::Unsyntax => "unsyntax",
+            Prefix::UnsyntaxSplicing => "unsyntax-splicing",
+            Prefix::Deref => "deref",
+            Prefix::AnonymousFn => "fn",
+        }
+    }
+
+    pub fn is_quoting(&self) -> bool {
+        matches!(
+            self,
+            Prefix::Quote | Prefix::Quasiquote | Prefix::Syntax | Prefix::Quasisyntax
+        )
+    }
+
+    pub fn is_unquoting(&self) -> bool {
+        matches!(
+            self,
+            Prefix::Unquote | Prefix::UnquoteSplicing | Prefix::Unsyntax | Prefix::UnsyntaxSplicing
+        )
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum PrettyExpr<T = ()> {
     Atom(String),
     Stat(&'static str),
-    Quote(Box<PrettyExpr<T>>),
-    Inline(Vec<PrettyExpr<T>>),
-    Expand(Vec<PrettyExpr<T>>),
+    Comment(String),
+    Hole,
+    Quote(Prefix, Box<PrettyExpr<T>>),
+    Dotted(Box<PrettyExpr<T>>),
+    Inline(Delimiter, Vec<PrettyExpr<T>>),
+    Expand(Delimiter, Vec<PrettyExpr<T>>),
     Style(T, Box<PrettyExpr<T>>),
+    Folded(Box<PrettyExpr<T>>),
 }
 
 impl<T> PrettyExpr<T> {
     pub fn empty_list() -> Self {
-        PrettyExpr::Inline(vec![])
+        PrettyExpr::Inline(Delimiter::List, vec![])
     }
     pub fn list(xs: Vec<PrettyExpr<T>>) -> Self {
-        PrettyExpr::Inline(xs)
+        PrettyExpr::Inline(Delimiter::List, xs)
+    }
+    pub fn bracketed(delimiter: Delimiter, xs: Vec<PrettyExpr<T>>) -> Self {
+        PrettyExpr::Inline(delimiter, xs)
     }
-    pub fn quote(x: PrettyExpr<T>) -> Self {
-        PrettyExpr::Quote(Box::new(x))
+    pub fn prefixed(prefix: Prefix, x: PrettyExpr<T>) -> Self {
+        PrettyExpr::Quote(prefix, Box::new(x))
+    }
+    pub fn dotted(x: PrettyExpr<T>) -> Self {
+        PrettyExpr::Dotted(Box::new(x))
     }
 
     pub fn styled(style: impl Into<T>, exp: impl Into<PrettyExpr<T>>) -> Self {
@@ -36,10 +153,18 @@ impl<T> PrettyExpr<T> {
         match (path, self) {
             (_, Style(s, x)) => Some(Self::styled(s, x.with_style(path, style)?)),
             ([], x) => Some(Self::styled(style, x)),
-            ([p, rest @ ..], Inline(xs)) => Self::list_with_style(xs, *p, rest, style).map(Inline),
-            ([p, rest @ ..], Expand(xs)) => Self::list_with_style(xs, *p, rest, style).map(Expand),
-            ([_, rest @ ..], Quote(x)) => x.with_style(rest, style).map(Self::quote),
-            (_, Atom(_) | Stat(_)) => None,
+            (_, Folded(x)) => Some(Folded(x)),
+            ([p, rest @ ..], Inline(d, xs)) => {
+                Self::list_with_style(xs, *p, rest, style).map(|xs| Inline(d, xs))
+            }
+            ([p, rest @ ..], Expand(d, xs)) => {
+                Self::list_with_style(xs, *p, rest, style).map(|xs| Expand(d, xs))
+            }
+            ([_, rest @ ..], Quote(q, x)) => {
+                x.with_style(rest, style).map(|x| Self::prefixed(q, x))
+            }
+            ([_, rest @ ..], Dotted(x)) => x.with_style(rest, style).map(Self::dotted),
+            (_, Atom(_) | Stat(_) | Comment(_) | Hole) => None,
         }
     }
 
@@ -52,9 +177,10 @@ impl<T> PrettyExpr<T> {
         match (path, self) {
             (_, Style(_, x)) => x.get(path),
             ([], x) => Some(x),
-            (_, Quote(x)) => x.get(&path[1..]),
-            ([p, rest @ ..], Inline(xs) | Expand(xs)) => xs.get(*p).and_then(|x| x.get(rest)),
-            (_, Atom(_) | Stat(_)) => None,
+            (_, Folded(x)) => x.get(path),
+            (_, Quote(_, x) | Dotted(x)) => x.get(&path[1..]),
+            ([p, rest @ ..], Inline(_, xs) | Expand(_, xs)) => xs.get(*p).and_then(|x| x.get(rest)),
+            (_, Atom(_) | Stat(_) | Comment(_) | Hole) => None,
         }
     }
 
@@ -63,11 +189,44 @@ impl<T> PrettyExpr<T> {
         match (path, self) {
             (_, Style(_, x)) => x.get_mut(path),
             ([], x) => Some(x),
-            (_, Quote(x)) => x.get_mut(&path[1..]),
-            ([p, rest @ ..], Inline(xs) | Expand(xs)) => {
+            (_, Folded(x)) => x.get_mut(path),
+            (_, Quote(_, x) | Dotted(x)) => x.get_mut(&path[1..]),
+            ([p, rest @ ..], Inline(_, xs) | Expand(_, xs)) => {
                 xs.get_mut(*p).and_then(|x| x.get_mut(rest))
             }
-            (_, Atom(_) | Stat(_)) => None,
+            (_, Atom(_) | Stat(_) | Comment(_) | Hole) => None,
+        }
+    }
+
+    pub fn walk(&self, f: &mut impl FnMut(&[usize], &Self) -> bool) {
+        self.walk_from(&mut vec![], f)
+    }
+
+    fn walk_from(&self, path: &mut Vec<usize>, f: &mut impl FnMut(&[usize], &Self) -> bool) {
+        if let PrettyExpr::Style(_, x) = self {
+            return x.walk_from(path, f);
+        }
+        if f(path, self) {
+            self.walk_children(path, f);
+        }
+    }
+
+    fn walk_children(&self, path: &mut Vec<usize>, f: &mut impl FnMut(&[usize], &Self) -> bool) {
+        match self {
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.walk_children(path, f),
+            PrettyExpr::Quote(_, x) | PrettyExpr::Dotted(x) => {
+                path.push(0);
+                x.walk_from(path, f);
+                path.pop();
+            }
+            PrettyExpr::Inline(_, xs) | PrettyExpr::Expand(_, xs) => {
+                for (i, x) in xs.iter().enumerate() {
+                    path.push(i);
+                    x.walk_from(path, f);
+                    path.pop();
+                }
+            }
+            _ => {}
         }
     }
 
@@ -92,10 +251,72 @@ impl<T> PrettyExpr<T> {
 
     pub fn is_atom(&self) -> bool {
         match self {
-            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) => true,
-            PrettyExpr::Quote(_) => false,
-            PrettyExpr::Inline(_) | PrettyExpr::Expand(_) => false,
-            PrettyExpr::Style(_, x) => x.is_atom(),
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => true,
+            PrettyExpr::Quote(..) | PrettyExpr::Dotted(_) => false,
+            PrettyExpr::Inline(..) | PrettyExpr::Expand(..) => false,
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.is_atom(),
+        }
+    }
+
+    pub fn is_comment(&self) -> bool {
+        match self {
+            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) | PrettyExpr::Hole => false,
+            PrettyExpr::Comment(_) => true,
+            PrettyExpr::Quote(..) | PrettyExpr::Dotted(_) => false,
+            PrettyExpr::Inline(..) | PrettyExpr::Expand(..) => false,
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.is_comment(),
+        }
+    }
+
+    pub fn is_hole(&self) -> bool {
+        match self {
+            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) | PrettyExpr::Comment(_) => false,
+            PrettyExpr::Hole => true,
+            PrettyExpr::Quote(..) | PrettyExpr::Dotted(_) => false,
+            PrettyExpr::Inline(..) | PrettyExpr::Expand(..) => false,
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.is_hole(),
+        }
+    }
+
+    pub fn is_folded(&self) -> bool {
+        match self {
+            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) | PrettyExpr::Comment(_) => false,
+            PrettyExpr::Hole => false,
+            PrettyExpr::Quote(..) | PrettyExpr::Dotted(_) => false,
+            PrettyExpr::Inline(..) | PrettyExpr::Expand(..) => false,
+            PrettyExpr::Style(_, x) => x.is_folded(),
+            PrettyExpr::Folded(_) => true,
+        }
+    }
+
+    pub fn set_folded(&mut self, folded: bool) {
+        match self {
+            PrettyExpr::Style(_, x) => x.set_folded(folded),
+            PrettyExpr::Folded(x) if !folded => {
+                let x = std::mem::replace(x.as_mut(), PrettyExpr::empty_list());
+                *self = x;
+            }
+            x if folded && !x.is_folded() => {
+                let y = std::mem::replace(x, PrettyExpr::empty_list());
+                *x = PrettyExpr::Folded(Box::new(y));
+            }
+            _ => {}
+        }
+    }
+
+    pub fn unfold_all(&mut self) {
+        self.set_folded(false);
+        match self {
+            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) | PrettyExpr::Comment(_) => {}
+            PrettyExpr::Hole => {}
+            PrettyExpr::Quote(_, x) | PrettyExpr::Dotted(x) => x.unfold_all(),
+            PrettyExpr::Inline(_, xs) | PrettyExpr::Expand(_, xs) => {
+                xs.iter_mut().for_each(PrettyExpr::unfold_all)
+            }
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.unfold_all(),
         }
     }
 
@@ -103,12 +324,19 @@ impl<T> PrettyExpr<T> {
         self.quoted_value().is_some()
     }
 
+    pub fn is_tail(&self) -> bool {
+        self.tail_value().is_some()
+    }
+
     pub fn is_empty_list(&self) -> bool {
         match self {
-            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) => false,
-            PrettyExpr::Quote(_) => false,
-            PrettyExpr::Inline(xs) | PrettyExpr::Expand(xs) => xs.is_empty(),
-            PrettyExpr::Style(_, x) => x.is_empty_list(),
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => false,
+            PrettyExpr::Quote(..) | PrettyExpr::Dotted(_) => false,
+            PrettyExpr::Inline(_, xs) | PrettyExpr::Expand(_, xs) => xs.is_empty(),
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.is_empty_list(),
         }
     }
 
@@ -116,69 +344,239 @@ impl<T> PrettyExpr<T> {
         match self {
             PrettyExpr::Atom(s) => Some(s),
             PrettyExpr::Stat(s) => Some(s),
-            PrettyExpr::Quote(_) => None,
-            PrettyExpr::Inline(xs) | PrettyExpr::Expand(xs) if xs.is_empty() => Some(""),
-            PrettyExpr::Inline(_) | PrettyExpr::Expand(_) => None,
-            PrettyExpr::Style(_, x) => x.get_text(),
+            PrettyExpr::Comment(s) => Some(s),
+            PrettyExpr::Hole => Some(""),
+            PrettyExpr::Quote(..) | PrettyExpr::Dotted(_) => None,
+            PrettyExpr::Inline(_, xs) | PrettyExpr::Expand(_, xs) if xs.is_empty() => Some(""),
+            PrettyExpr::Inline(..) | PrettyExpr::Expand(..) => None,
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.get_text(),
         }
     }
 
     pub fn quoted_value(&self) -> Option<&Self> {
         match self {
-            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) => None,
-            PrettyExpr::Quote(x) => Some(x),
-            PrettyExpr::Inline(_) | PrettyExpr::Expand(_) => None,
-            PrettyExpr::Style(_, x) => x.quoted_value(),
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => None,
+            PrettyExpr::Quote(_, x) => Some(x),
+            PrettyExpr::Dotted(_) => None,
+            PrettyExpr::Inline(..) | PrettyExpr::Expand(..) => None,
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.quoted_value(),
+        }
+    }
+
+    pub fn prefix(&self) -> Option<Prefix> {
+        match self {
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => None,
+            PrettyExpr::Quote(q, _) => Some(*q),
+            PrettyExpr::Dotted(_) => None,
+            PrettyExpr::Inline(..) | PrettyExpr::Expand(..) => None,
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.prefix(),
+        }
+    }
+
+    pub fn set_prefix(&mut self, prefix: Prefix) -> bool {
+        match self {
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => false,
+            PrettyExpr::Quote(q, _) => {
+                *q = prefix;
+                true
+            }
+            PrettyExpr::Dotted(_) => false,
+            PrettyExpr::Inline(..) | PrettyExpr::Expand(..) => false,
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.set_prefix(prefix),
+        }
+    }
+
+    pub fn tail_value(&self) -> Option<&Self> {
+        match self {
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => None,
+            PrettyExpr::Quote(..) => None,
+            PrettyExpr::Dotted(x) => Some(x),
+            PrettyExpr::Inline(..) | PrettyExpr::Expand(..) => None,
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.tail_value(),
+        }
+    }
+
+    pub fn set_text(&mut self, text: String) {
+        match self {
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.set_text(text),
+            x if text.starts_with(';') => *x = PrettyExpr::Comment(text),
+            x => *x = PrettyExpr::Atom(text),
+        }
+    }
+
+    pub fn delimiter(&self) -> Option<Delimiter> {
+        match self {
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => None,
+            PrettyExpr::Quote(..) | PrettyExpr::Dotted(_) => None,
+            PrettyExpr::Inline(d, _) | PrettyExpr::Expand(d, _) => Some(*d),
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.delimiter(),
+        }
+    }
+
+    pub fn set_delimiter(&mut self, delimiter: Delimiter) -> bool {
+        match self {
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => false,
+            PrettyExpr::Quote(..) | PrettyExpr::Dotted(_) => false,
+            PrettyExpr::Inline(d, _) | PrettyExpr::Expand(d, _) => {
+                *d = delimiter;
+                true
+            }
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.set_delimiter(delimiter),
         }
     }
 
     pub fn elements(&self) -> Option<&[Self]> {
         match self {
-            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) => None,
-            PrettyExpr::Quote(_) => None,
-            PrettyExpr::Inline(xs) | PrettyExpr::Expand(xs) => Some(xs.as_slice()),
-            PrettyExpr::Style(_, x) => x.elements(),
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => None,
+            PrettyExpr::Quote(..) | PrettyExpr::Dotted(_) => None,
+            PrettyExpr::Inline(_, xs) | PrettyExpr::Expand(_, xs) => Some(xs.as_slice()),
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.elements(),
         }
     }
 
     pub fn elements_mut(&mut self) -> Option<&mut Vec<Self>> {
         match self {
-            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) => None,
-            PrettyExpr::Quote(_) => None,
-            PrettyExpr::Inline(xs) | PrettyExpr::Expand(xs) => Some(xs),
-            PrettyExpr::Style(_, x) => x.elements_mut(),
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => None,
+            PrettyExpr::Quote(..) | PrettyExpr::Dotted(_) => None,
+            PrettyExpr::Inline(_, xs) | PrettyExpr::Expand(_, xs) => Some(xs),
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.elements_mut(),
         }
     }
 
     pub fn remove_item(&mut self, idx: usize) -> Option<Self> {
         match self {
-            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) => None,
-            PrettyExpr::Quote(x) => Some(std::mem::replace(x, PrettyExpr::list(vec![]))),
-            PrettyExpr::Inline(xs) | PrettyExpr::Expand(xs) => Some(xs.remove(idx)),
-            PrettyExpr::Style(_, x) => x.remove_item(idx),
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => None,
+            PrettyExpr::Quote(_, x) | PrettyExpr::Dotted(x) => {
+                Some(std::mem::replace(x, PrettyExpr::list(vec![])))
+            }
+            PrettyExpr::Inline(_, xs) | PrettyExpr::Expand(_, xs) => Some(xs.remove(idx)),
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.remove_item(idx),
         }
     }
 
     pub fn len(&self) -> usize {
         match self {
-            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) => 0,
-            PrettyExpr::Quote(_) => 1,
-            PrettyExpr::Inline(xs) | PrettyExpr::Expand(xs) => xs.len(),
-            PrettyExpr::Style(_, x) => x.len(),
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => 0,
+            PrettyExpr::Quote(..) | PrettyExpr::Dotted(_) => 1,
+            PrettyExpr::Inline(_, xs) | PrettyExpr::Expand(_, xs) => xs.len(),
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.len(),
         }
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
     fn inline_width(&self) -> usize {
         match self {
             PrettyExpr::Atom(x) => x.len(),
             PrettyExpr::Stat(x) => x.len(),
-            PrettyExpr::Quote(x) => 1 + x.inline_width(),
-            PrettyExpr::Inline(xs) => {
+            PrettyExpr::Comment(x) => x.len(),
+            PrettyExpr::Hole => HOLE.len(),
+            PrettyExpr::Quote(q, x) => q.text().len() + x.inline_width(),
+            PrettyExpr::Dotted(x) => 2 + x.inline_width(),
+            PrettyExpr::Inline(d, xs) | PrettyExpr::Expand(d, xs) => {
                 let n_spaces = if xs.len() < 2 { 0 } else { xs.len() - 1 };
-                2 + xs.iter().map(PrettyExpr::inline_width).sum::<usize>() + n_spaces
+                d.open().len()
+                    + d.close().len()
+                    + xs.iter().map(PrettyExpr::inline_width).sum::<usize>()
+                    + n_spaces
             }
             PrettyExpr::Style(_, x) => x.inline_width(),
-            PrettyExpr::Expand(_) => unimplemented!(),
+            PrettyExpr::Folded(x) => x.folded_width(),
+        }
+    }
+
+    fn folded_width(&self) -> usize {
+        match self {
+            PrettyExpr::Inline(d, xs) | PrettyExpr::Expand(d, xs) => {
+                let items = xs.iter().filter(|x| !x.is_comment()).take(FOLD_ITEMS);
+                d.open().len()
+                    + d.close().len()
+                    + items.map(|x| x.inline_width() + 1).sum::<usize>()
+                    + FOLD_MARKER.chars().count()
+            }
+            PrettyExpr::Quote(q, x) => q.text().len() + x.folded_width(),
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.folded_width(),
+            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) | PrettyExpr::Comment(_) => {
+                self.inline_width()
+            }
+            PrettyExpr::Hole | PrettyExpr::Dotted(_) => self.inline_width(),
+        }
+    }
+
+    fn forces_expansion(&self) -> bool {
+        match self {
+            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) | PrettyExpr::Hole => false,
+            PrettyExpr::Comment(_) => true,
+            PrettyExpr::Quote(_, x) | PrettyExpr::Dotted(x) => x.forces_expansion(),
+            PrettyExpr::Inline(_, xs) => xs.iter().any(PrettyExpr::forces_expansion),
+            PrettyExpr::Expand(..) => true,
+            PrettyExpr::Style(_, x) => x.forces_expansion(),
+            PrettyExpr::Folded(_) => false,
+        }
+    }
+
+    pub fn set_expanded(&mut self, expanded: bool) -> bool {
+        match self {
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => false,
+            PrettyExpr::Quote(_, x) | PrettyExpr::Dotted(x) => x.set_expanded(expanded),
+            PrettyExpr::Inline(d, xs) | PrettyExpr::Expand(d, xs) => {
+                let xs = std::mem::take(xs);
+                *self = match expanded {
+                    true => PrettyExpr::Expand(*d, xs),
+                    false => PrettyExpr::Inline(*d, xs),
+                };
+                true
+            }
+            PrettyExpr::Style(_, x) | PrettyExpr::Folded(x) => x.set_expanded(expanded),
+        }
+    }
+
+    pub fn is_expanded(&self) -> bool {
+        match self {
+            PrettyExpr::Atom(_)
+            | PrettyExpr::Stat(_)
+            | PrettyExpr::Comment(_)
+            | PrettyExpr::Hole => false,
+            PrettyExpr::Quote(_, x) | PrettyExpr::Dotted(x) => x.is_expanded(),
+            PrettyExpr::Inline(..) => false,
+            PrettyExpr::Expand(..) => true,
+            PrettyExpr::Style(_, x) => x.is_expanded(),
+            PrettyExpr::Folded(_) => false,
         }
     }
 }
@@ -205,7 +603,7 @@ pub struct Pretty<T> {
 
 impl<T> Pretty<T> {
     pub fn write<F: Formatter<T>>(&self, f: &mut F) -> Result<(), F::Error> {
-        self.pf.write(&self.pe, 0, f)
+        self.pf.write(&self.pe, 0, 0, f)
     }
 
     pub fn with_style(self, path: &[usize], style: impl Into<T>) -> Option<Self> {
@@ -231,25 +629,59 @@ impl PrettyFormatter {
         match pe {
             PrettyExpr::Atom(x) => PrettyExpr::Atom(x),
             PrettyExpr::Stat(x) => PrettyExpr::Stat(x),
-            PrettyExpr::Inline(_) if current_indent + pe.inline_width() <= self.max_code_width => {
+            PrettyExpr::Comment(x) => PrettyExpr::Comment(x),
+            PrettyExpr::Hole => PrettyExpr::Hole,
+            PrettyExpr::Inline(..)
+                if !pe.forces_expansion()
+                    && current_indent + pe.inline_width() <= self.max_code_width =>
+            {
                 pe
             }
-            PrettyExpr::Inline(xs) | PrettyExpr::Expand(xs) => {
+            PrettyExpr::Inline(Delimiter::Map, xs) | PrettyExpr::Expand(Delimiter::Map, xs) => {
+                let indent = current_indent + Delimiter::Map.open().len();
+                PrettyExpr::Expand(Delimiter::Map, self.prepare_map(xs, indent))
+            }
+            PrettyExpr::Inline(d, xs) | PrettyExpr::Expand(d, xs) => {
                 let indent = current_indent
                     + xs.first()
-                        .map(|x0| self.compute_expand_indent(x0))
+                        .map(|x0| self.compute_expand_indent(d, x0))
                         .unwrap_or(0);
                 PrettyExpr::Expand(
+                    d,
                     xs.into_iter()
                         .map(|x| self.prepare_recursively(x, indent))
                         .collect(),
                 )
             }
-            PrettyExpr::Quote(x) => PrettyExpr::quote(self.prepare_recursively(*x, current_indent)),
+            PrettyExpr::Quote(q, x) => PrettyExpr::prefixed(
+                q,
+                self.prepare_recursively(*x, current_indent + q.text().len()),
+            ),
+            PrettyExpr::Dotted(x) => {
+                PrettyExpr::dotted(self.prepare_recursively(*x, current_indent + 2))
+            }
             PrettyExpr::Style(s, x) => {
                 PrettyExpr::styled(s, self.prepare_recursively(*x, current_indent))
             }
+            PrettyExpr::Folded(x) => PrettyExpr::Folded(x),
+        }
+    }
+
+    fn prepare_map<T>(&self, xs: Vec<PrettyExpr<T>>, indent: usize) -> Vec<PrettyExpr<T>> {
+        let mut out = Vec::with_capacity(xs.len());
+        let mut value_indent = None;
+        for x in xs {
+            if x.is_comment() {
+                out.push(x);
+            } else if let Some(value_indent) = value_indent.take() {
+                out.push(self.prepare_recursively(x, value_indent));
+            } else {
+                let key = self.prepare_recursively(x, indent);
+                value_indent = Some(self.map_value_indent(&key, indent));
+                out.push(key);
+            }
         }
+        out
     }
 
     pub fn pretty<T>(&self, pe: PrettyExpr<T>) -> Pretty<T> {
@@ -263,74 +695,218 @@ impl PrettyFormatter {
         &self,
         pe: &PrettyExpr<T>,
         indent_level: usize,
+        depth: usize,
         f: &mut F,
     ) -> Result<(), F::Error> {
         match pe {
             PrettyExpr::Atom(x) => f.write(x),
             PrettyExpr::Stat(x) => f.write(x),
-            PrettyExpr::Quote(x) => {
-                f.write("'")?;
-                self.write(x, indent_level + 1, f)
+            PrettyExpr::Comment(x) => f.write(x),
+            PrettyExpr::Hole => f.write(HOLE),
+            PrettyExpr::Quote(q, x) => {
+                f.write(q.text())?;
+                self.write(x, indent_level + q.text().len(), depth, f)
+            }
+            PrettyExpr::Dotted(x) => {
+                f.write(". ")?;
+                self.write(x, indent_level + 2, depth, f)
+            }
+            PrettyExpr::Inline(d, xs) => self.write_inline(*d, xs, depth, f),
+            PrettyExpr::Expand(Delimiter::Map, xs) => {
+                self.write_expanded_map(xs, indent_level, depth, f)
+            }
+            PrettyExpr::Expand(d, xs) => self.write_expanded(*d, xs, indent_level, depth, f),
+            PrettyExpr::Style(s, x) => {
+                f.save_style();
+                f.set_style(s);
+                self.write(x, indent_level, depth, f)?;
+                f.restore_style();
+                Ok(())
+            }
+            PrettyExpr::Folded(x) => self.write_folded(x, depth, f),
+        }
+    }
+
+    fn write_folded<T, F: Formatter<T>>(
+        &self,
+        pe: &PrettyExpr<T>,
+        depth: usize,
+        f: &mut F,
+    ) -> Result<(), F::Error> {
+        match pe {
+            PrettyExpr::Inline(d, xs) | PrettyExpr::Expand(d, xs) => {
+                f.write_delimiter(d.open(), depth)?;
+                for x in xs.iter().filter(|x| !x.is_comment()).take(FOLD_ITEMS) {
+                    self.write_flat(x, depth + 1, f)?;
+                    f.write(" ")?;
+                }
+                f.write_fold_marker(FOLD_MARKER)?;
+                f.write_delimiter(d.close(), depth)
+            }
+            PrettyExpr::Quote(q, x) => {
+                f.write(q.text())?;
+                self.write_folded(x, depth, f)
             }
-            PrettyExpr::Inline(xs) => self.write_inline(xs, f),
-            PrettyExpr::Expand(xs) => self.write_expanded(xs, indent_level, f),
             PrettyExpr::Style(s, x) => {
                 f.save_style();
                 f.set_style(s);
-                self.write(x, indent_level, f)?;
+                self.write_folded(x, depth, f)?;
                 f.restore_style();
                 Ok(())
             }
+            PrettyExpr::Folded(x) => self.write_folded(x, depth, f),
+            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) | PrettyExpr::Comment(_) => {
+                self.write_flat(pe, depth, f)
+            }
+            PrettyExpr::Hole | PrettyExpr::Dotted(_) => self.write_flat(pe, depth, f),
+        }
+    }
+
+    fn write_flat<T, F: Formatter<T>>(
+        &self,
+        pe: &PrettyExpr<T>,
+        depth: usize,
+        f: &mut F,
+    ) -> Result<(), F::Error> {
+        match pe {
+            PrettyExpr::Atom(_) | PrettyExpr::Stat(_) | PrettyExpr::Comment(_) => {
+                self.write(pe, 0, depth, f)
+            }
+            PrettyExpr::Hole => self.write(pe, 0, depth, f),
+            PrettyExpr::Quote(q, x) => {
+                f.write(q.text())?;
+                self.write_flat(x, depth, f)
+            }
+            PrettyExpr::Dotted(x) => {
+                f.write(". ")?;
+                self.write_flat(x, depth, f)
+            }
+            PrettyExpr::Inline(d, xs) | PrettyExpr::Expand(d, xs) => {
+                f.write_delimiter(d.open(), depth)?;
+                for (i, x) in xs.iter().enumerate() {
+                    if i > 0 {
+                        f.write(" ")?;
+                    }
+                    self.write_flat(x, depth + 1, f)?;
+                }
+                f.write_delimiter(d.close(), depth)
+            }
+            PrettyExpr::Style(s, x) => {
+                f.save_style();
+                f.set_style(s);
+                self.write_flat(x, depth, f)?;
+                f.restore_style();
+                Ok(())
+            }
+            PrettyExpr::Folded(x) => self.write_folded(x, depth, f),
         }
     }
 
     fn write_inline<T, F: Formatter<T>>(
         &self,
+        delimiter: Delimiter,
         xs: &[PrettyExpr<T>],
+        depth: usize,
         f: &mut F,
     ) -> Result<(), F::Error> {
-        f.write("(")?;
-        match &xs[..] {
+        f.write_delimiter(delimiter.open(), depth)?;
+        match xs {
             [] => {}
-            [x] => self.write(x, 0, f)?,
+            [x] => self.write(x, 0, depth + 1, f)?,
             [x, ys @ ..] => {
-                self.write(x, 0, f)?;
+                self.write(x, 0, depth + 1, f)?;
                 for y in ys {
                     f.write(" ")?;
-                    self.write(y, 0, f)?;
+                    self.write(y, 0, depth + 1, f)?;
                 }
             }
         }
-        f.write(")")
+        f.write_delimiter(delimiter.close(), depth)
     }
 
     fn write_expanded<T, F: Formatter<T>>(
         &self,
+        delimiter: Delimiter,
         xs: &[PrettyExpr<T>],
         mut indent_level: usize,
+        depth: usize,
         f: &mut F,
     ) -> Result<(), F::Error> {
-        f.write("(")?;
-        match &xs[..] {
+        f.write_delimiter(delimiter.open(), depth)?;
+        match xs {
             [] => {}
             [x] => {
-                indent_level += self.compute_expand_indent(x);
-                self.write(x, indent_level, f)?
+                indent_level += self.compute_expand_indent(delimiter, x);
+                self.write(x, indent_level, depth + 1, f)?
             }
             [x, ys @ ..] => {
-                indent_level += self.compute_expand_indent(x);
-                self.write(x, indent_level, f)?;
+                indent_level += self.compute_expand_indent(delimiter, x);
+                self.write(x, indent_level, depth + 1, f)?;
                 for y in ys {
                     f.write_indent(indent_level)?;
-                    self.write(y, indent_level, f)?;
+                    self.write(y, indent_level, depth + 1, f)?;
                 }
             }
         }
-        f.write(")")
+        if xs.last().map(PrettyExpr::is_comment).unwrap_or(false) {
+            f.write_indent(indent_level)?;
+        }
+        f.write_delimiter(delimiter.close(), depth)
     }
 
-    fn compute_expand_indent<T>(&self, first_item: &PrettyExpr<T>) -> usize {
-        if first_item.is_atom() {
+    fn write_expanded_map<T, F: Formatter<T>>(
+        &self,
+        xs: &[PrettyExpr<T>],
+        mut indent_level: usize,
+        depth: usize,
+        f: &mut F,
+    ) -> Result<(), F::Error> {
+        f.write_delimiter(Delimiter::Map.open(), depth)?;
+        indent_level += Delimiter::Map.open().len();
+        let mut key = None;
+        let mut after_comment = false;
+        for (i, x) in xs.iter().enumerate() {
+            match key {
+                Some(k) if !x.is_comment() => {
+                    let value_indent = self.map_value_indent(k, indent_level);
+                    if k.is_expanded() || after_comment {
+                        f.write_indent(value_indent)?;
+                    } else {
+                        f.write(" ")?;
+                    }
+                    self.write(x, value_indent, depth + 1, f)?;
+                    key = None;
+                }
+                _ => {
+                    if i > 0 {
+                        f.write_indent(indent_level)?;
+                    }
+                    self.write(x, indent_level, depth + 1, f)?;
+                    if !x.is_comment() {
+                        key = Some(x);
+                    }
+                }
+            }
+            after_comment = x.is_comment();
+        }
+        if after_comment {
+            f.write_indent(indent_level)?;
+        }
+        f.write_delimiter(Delimiter::Map.close(), depth)
+    }
+
+    fn map_value_indent<T>(&self, key: &PrettyExpr<T>, indent: usize) -> usize {
+        if key.is_expanded() {
+            indent
+        } else {
+            indent + key.inline_width() + 1
+        }
+    }
+
+    fn compute_expand_indent<T>(&self, delimiter: Delimiter, first_item: &PrettyExpr<T>) -> usize {
+        if delimiter != Delimiter::List {
+            delimiter.open().len()
+        } else if first_item.is_atom() {
             self.default_indent
         } else {
             1
@@ -343,7 +919,7 @@ impl<T: Clone> std::fmt::Display for PrettyExpr<T> {
         let pf = PrettyFormatter::default();
         let pe = pf.prepare(self.clone());
         let mut df = DisplayFormatter::new(f);
-        pf.write(&pe, 0, &mut df)
+        pf.write(&pe, 0, 0, &mut df)
     }
 }
 
@@ -351,7 +927,7 @@ impl<T: Clone> std::fmt::Display for Pretty<T> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         let pe = self.pf.prepare(self.pe.clone());
         let mut df = DisplayFormatter::new(f);
-        self.pf.write(&pe, 0, &mut df)
+        self.pf.write(&pe, 0, 0, &mut df)
     }
 }
 
@@ -367,6 +943,18 @@ pub trait Formatter<S> {
         self.write("\n")
     }
 
+    fn write_delimiter(
+        &mut self,
+        delimiter: &str,
+        _depth: usize,
+    ) -> std::result::Result<(), Self::Error> {
+        self.write(delimiter)
+    }
+
+    fn write_fold_marker(&mut self, marker: &str) -> std::result::Result<(), Self::Error> {
+        self.write(marker)
+    }
+
     fn write_indent(&mut self, level: usize) -> std::result::Result<(), Self::Error> {
         self.write_newline()?;
         self.write(" ".repeat(level))
@@ -396,6 +984,17 @@ impl<S> Formatter<S> for DisplayFormatter<'_, '_, S> {
     fn restore_style(&mut self) {}
 }
 
+pub fn format_path(path: &[usize]) -> String {
+    path.iter()
+        .map(|i| i.to_string())
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+pub fn parse_path(text: &str) -> Option<Vec<usize>> {
+    text.split('.').map(|i| i.parse().ok()).collect()
+}
+
 #[test]
 fn tests() {
     macro_rules! p {
@@ -439,3 +1038,153 @@ fn tests() {
         "(((((alpha\n      (beta\n        gamma)\n      delta)))))"
     );
 }
+
+#[test]
+fn delimiters() {
+    macro_rules! p {
+        ($($x:tt)*) => {pe!($($x)*) as PrettyExpr::<()>};
+    }
+
+    assert_eq!(p![[a b]].inline_width(), 5);
+    assert_eq!(
+        PrettyExpr::<()>::bracketed(Delimiter::Set, vec![p![a]]).inline_width(),
+        4
+    );
+
+    let pf = PrettyFormatter::new(15, 2);
+
+    assert_eq!(pf.pretty(p![[a b c]]).to_string(), "[a b c]");
+    assert_eq!(
+        pf.pretty(p![[alpha beta gamma delta]]).to_string(),
+        "[alpha\n beta\n gamma\n delta]"
+    );
+    assert_eq!(
+        pf.pretty(p![{alpha 1 beta (gamma delta)}]).to_string(),
+        "{alpha 1\n beta (gamma\n        delta)}"
+    );
+    assert_eq!(
+        pf.pretty(p![(let [a 1 b 2] {a b})]).to_string(),
+        "(let\n  [a 1 b 2]\n  {a b})"
+    );
+}
+
+#[test]
+fn map_comments() {
+    let pf = PrettyFormatter::new(80, 2);
+    let read = |text: &str| crate::reader::read_all::<()>(text).unwrap();
+    for (text, expected) in [
+        ("{:a 1 ; c\n :b 2}", "{:a 1\n ; c\n :b 2}"),
+        ("{:a 1 :b 2 ; trailing\n}", "{:a 1\n :b 2\n ; trailing\n }"),
+        ("{:a ; c\n 1 :b 2}", "{:a\n ; c\n    1\n :b 2}"),
+    ] {
+        let xs = read(text);
+        let formatted = pf.pretty(xs[0].clone()).to_string();
+        assert_eq!(formatted, expected);
+        assert_eq!(format!("{:?}", read(&formatted)), format!("{:?}", xs));
+    }
+}
+
+#[test]
+fn dotted() {
+    let p = PrettyExpr::<()>::list(vec![pe!(a), pe!(b), PrettyExpr::dotted(pe!(rest))]);
+    assert_eq!(p.inline_width(), 12);
+    assert_eq!(p.get(&[2, 0]).unwrap().get_text(), Some("rest"));
+    assert!(p.get(&[2]).unwrap().tail_value().is_some());
+
+    assert_eq!(
+        PrettyFormatter::new(15, 2).pretty(p.clone()).to_string(),
+        "(a b . rest)"
+    );
+    assert_eq!(
+        PrettyFormatter::new(10, 2).pretty(p).to_string(),
+        "(a\n  b\n  . rest)"
+    );
+}
+
+#[test]
+fn folded() {
+    let mut p: PrettyExpr = pe![(define (foo x) (let ((y x)) (bar y)))];
+    p.set_folded(true);
+    assert!(p.is_folded());
+    assert_eq!(p.len(), 3);
+    assert_eq!(p.get(&[1, 0]).unwrap().get_text(), Some("foo"));
+    assert_eq!(p.inline_width(), 18);
+    assert_eq!(p.to_string(), "(define (foo x) …)");
+
+    let mut q: PrettyExpr = PrettyExpr::list(vec![pe!(f), p.clone(), pe!(z)]);
+    assert_eq!(q.to_string(), "(f\n  (define (foo x) …)\n  z)");
+    q.get_mut(&[1, 2]).unwrap().set_folded(true);
+    q.unfold_all();
+    assert!(!q.get(&[1]).unwrap().is_folded());
+    assert!(!q.get(&[1, 2]).unwrap().is_folded());
+
+    p.set_folded(false);
+    assert!(!p.is_folded());
+}
+
+#[test]
+fn expanded() {
+    let mut p: PrettyExpr = pe![(f (g x) y)];
+    assert!(p.get_mut(&[1]).unwrap().set_expanded(true));
+    assert!(p.get(&[1]).unwrap().is_expanded());
+    assert!(!p.get_mut(&[2]).unwrap().set_expanded(true));
+
+    let pf = PrettyFormatter::new(80, 2);
+    assert_eq!(pf.pretty(p.clone()).to_string(), "(f\n  (g\n    x)\n  y)");
+    p.get_mut(&[1]).unwrap().set_expanded(false);
+    assert_eq!(pf.pretty(p).to_string(), "(f (g x) y)");
+}
+
+#[test]
+fn delimiter_depth() {
+    struct DepthFormatter(String);
+
+    impl Formatter<()> for DepthFormatter {
+        type Error = ();
+
+        fn write(&mut self, x: impl std::fmt::Display) -> Result<(), ()> {
+            self.0 += &x.to_string();
+            Ok(())
+        }
+
+        fn write_delimiter(&mut self, delimiter: &str, depth: usize) -> Result<(), ()> {
+            self.0 += &format!("{}{}", delimiter, depth);
+            Ok(())
+        }
+
+        fn set_style(&mut self, _style: &()) {}
+        fn save_style(&mut self) {}
+        fn restore_style(&mut self) {}
+    }
+
+    let mut f = DepthFormatter(String::new());
+    PrettyFormatter::new(15, 2)
+        .pretty(pe![(a [b (c)] d)])
+        .write(&mut f)
+        .unwrap();
+    assert_eq!(f.0, "(0a [1b (2c)2]1 d)0");
+}
+
+#[test]
+fn walk() {
+    let p: PrettyExpr = pe![(let ((a 1)) (f a "b"))];
+    let mut found = vec![];
+    p.walk(&mut |path, x| {
+        if x.get_text() == Some("a") {
+            found.push(path.to_vec());
+        }
+        true
+    });
+    assert_eq!(found, vec![vec![1, 0, 0], vec![2, 1]]);
+
+    let mut visited = 0;
+    p.walk(&mut |path, _| {
+        visited += 1;
+        path.is_empty()
+    });
+    assert_eq!(visited, 4);
+
+    assert_eq!(format_path(&[0, 2, 1]), "0.2.1");
+    assert_eq!(parse_path("0.2.1"), Some(vec![0, 2, 1]));
+    assert_eq!(parse_path("0.x"), None);
+}
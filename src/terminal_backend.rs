@@ -1,5 +1,6 @@
+use crate::sxfmt::{Delimiter, Prefix};
 use crate::{styles, textbuffer, RenderTarget};
-use crossterm::event::KeyEvent;
+use crossterm::event::{KeyEvent, KeyModifiers};
 use crossterm::style::Stylize;
 use crossterm::{cursor, queue, style, style::ContentStyle};
 use std::io::{Result, Stdout, Write};
@@ -22,6 +23,13 @@ impl RenderTarget for Stdout {
         let s = adapt_style(s);
         queue!(self, style::PrintStyledContent(s.apply(ch)))
     }
+
+    fn set_cursor(&mut self, pos: Option<(usize, usize)>) -> Result<()> {
+        match pos {
+            Some((x, y)) => queue!(self, cursor::MoveTo(x as u16, y as u16), cursor::Show),
+            None => queue!(self, cursor::Hide),
+        }
+    }
 }
 
 fn adapt_style(s: &styles::Style) -> style::ContentStyle {
@@ -31,6 +39,35 @@ fn adapt_style(s: &styles::Style) -> style::ContentStyle {
         Background => ContentStyle::new().dark_green().on_dark_grey().bold(),
         Frame => ContentStyle::new().black().on_dark_grey(),
         Highlight => ContentStyle::new().black().on_dark_green(),
+        Editing => ContentStyle::new().white().on_dark_grey().underlined(),
+        SpecialForm => ContentStyle::new().magenta().on_dark_grey().bold(),
+        Number => ContentStyle::new().cyan().on_dark_grey(),
+        StringLiteral => ContentStyle::new().yellow().on_dark_grey(),
+        Quoted => ContentStyle::new().green().on_dark_grey(),
+        Keyword => ContentStyle::new().blue().on_dark_grey(),
+        Comment => ContentStyle::new().black().on_dark_grey().italic(),
+        Hole => ContentStyle::new().dark_yellow().on_dark_grey().bold(),
+        FoldMarker => ContentStyle::new().black().on_dark_yellow(),
+        Enclosing => ContentStyle::new().white().on_dark_grey(),
+        MatchingDelimiter => ContentStyle::new().black().on_yellow().bold(),
+        Sibling => ContentStyle::new().grey().on_black(),
+        PathDepth => ContentStyle::new().black().on_grey(),
+        Mode => ContentStyle::new().black().on_dark_cyan().bold(),
+        Occurrence => ContentStyle::new().white().on_dark_cyan(),
+        SearchMatch => ContentStyle::new().black().on_yellow(),
+        Prompt => ContentStyle::new().white().on_dark_blue(),
+        Popup => ContentStyle::new().black().on_grey(),
+        PopupSelected => ContentStyle::new().white().on_dark_magenta(),
+        StatusBar => ContentStyle::new().black().on_grey(),
+        Message => ContentStyle::new().white().on_dark_blue().bold(),
+        MessageFading => ContentStyle::new().grey().on_dark_blue(),
+        Selection => ContentStyle::new().black().on_cyan(),
+        Rainbow1 => ContentStyle::new().red().on_dark_grey(),
+        Rainbow2 => ContentStyle::new().yellow().on_dark_grey(),
+        Rainbow3 => ContentStyle::new().green().on_dark_grey(),
+        Rainbow4 => ContentStyle::new().cyan().on_dark_grey(),
+        Rainbow5 => ContentStyle::new().blue().on_dark_grey(),
+        Rainbow6 => ContentStyle::new().magenta().on_dark_grey(),
     }
 }
 
@@ -39,17 +76,139 @@ pub fn adapt_event(e: crossterm::event::Event) -> crate::events::Event {
     use crossterm::event::Event as X;
     use crossterm::event::KeyCode::*;
     match e {
+        X::Key(KeyEvent {
+            code: Char('s'),
+            modifiers: KeyModifiers::CONTROL,
+        }) => Y::Save,
+        X::Key(KeyEvent {
+            code: Char('f'),
+            modifiers: KeyModifiers::CONTROL,
+        }) => Y::Search,
+        X::Key(KeyEvent {
+            code: Char('r'),
+            modifiers: KeyModifiers::CONTROL,
+        }) => Y::Replace,
+        X::Key(KeyEvent {
+            code: Char('x'),
+            modifiers: KeyModifiers::CONTROL,
+        }) => Y::Cut,
+        X::Key(KeyEvent {
+            code: Char('c'),
+            modifiers: KeyModifiers::CONTROL,
+        }) => Y::Copy,
+        X::Key(KeyEvent {
+            code: Char('v'),
+            modifiers: KeyModifiers::CONTROL,
+        }) => Y::Paste,
+        X::Key(KeyEvent {
+            code: Char('g'),
+            modifiers: KeyModifiers::CONTROL,
+        }) => Y::Command("goto"),
+        X::Key(KeyEvent {
+            code: Char('n'),
+            modifiers: KeyModifiers::CONTROL,
+        }) => Y::NavNextMatch,
+        X::Key(KeyEvent {
+            code: Char('p'),
+            modifiers: KeyModifiers::CONTROL,
+        }) => Y::NavPrevMatch,
+        X::Key(KeyEvent {
+            code: Char(ch),
+            modifiers,
+        }) if modifiers.contains(KeyModifiers::ALT) => match ch {
+            '(' => Y::EditDelimiter(Delimiter::List),
+            '[' => Y::EditDelimiter(Delimiter::Vector),
+            '{' => Y::EditDelimiter(Delimiter::Map),
+            '#' => Y::EditDelimiter(Delimiter::Set),
+            '\'' => Y::EditPrefix(Prefix::Syntax),
+            '`' => Y::EditPrefix(Prefix::Quasisyntax),
+            ',' => Y::EditPrefix(Prefix::Unsyntax),
+            'f' => Y::EditPrefix(Prefix::AnonymousFn),
+            's' => Y::EditSnippet,
+            'i' => Y::EditIndent,
+            'z' => Y::ToggleFold,
+            'u' => Y::UnfoldAll,
+            '=' => Y::ExpandRegion,
+            '-' => Y::ShrinkRegion,
+            '<' => Y::NavFirstSibling,
+            '>' => Y::NavLastSibling,
+            'm' => Y::ToggleModal,
+            'j' => Y::NavNextList,
+            'k' => Y::NavPrevList,
+            '0'..='9' => Y::FoldToDepth(ch as usize - '0' as usize),
+            'n' => Y::NavNextOccurrence,
+            'p' => Y::NavPrevOccurrence,
+            'q' => Y::ToggleQuotedOccurrences,
+            '.' => Y::NavDefinition,
+            'b' => Y::NavBack,
+            'x' => Y::Palette,
+            _ => Y::Unknown,
+        },
         X::Key(KeyEvent { code: Char(ch), .. }) => Y::Edit(ch),
         X::Key(KeyEvent {
             code: Backspace, ..
         }) => Y::EditBackspace,
         X::Key(KeyEvent { code: Delete, .. }) => Y::EditDelete,
+        X::Key(KeyEvent {
+            code: Up,
+            modifiers: KeyModifiers::ALT,
+        }) => Y::EditDragUp,
+        X::Key(KeyEvent {
+            code: Down,
+            modifiers: KeyModifiers::ALT,
+        }) => Y::EditDragDown,
+        X::Key(KeyEvent {
+            code: Left,
+            modifiers: KeyModifiers::ALT,
+        }) => Y::EditPromote,
+        X::Key(KeyEvent {
+            code: Right,
+            modifiers: KeyModifiers::ALT,
+        }) => Y::EditDemote,
+        X::Key(KeyEvent {
+            code: Up,
+            modifiers: KeyModifiers::CONTROL,
+        }) => Y::NavPrevSibling,
+        X::Key(KeyEvent {
+            code: Down,
+            modifiers: KeyModifiers::CONTROL,
+        }) => Y::NavNextSibling,
+        X::Key(KeyEvent {
+            code: Left,
+            modifiers: KeyModifiers::CONTROL,
+        }) => Y::NavTopLevel,
+        X::Key(KeyEvent {
+            code: Right,
+            modifiers: KeyModifiers::CONTROL,
+        }) => Y::NavLastChild,
+        X::Key(KeyEvent {
+            code: Up,
+            modifiers: KeyModifiers::SHIFT,
+        }) => Y::SelectUp,
+        X::Key(KeyEvent {
+            code: Down,
+            modifiers: KeyModifiers::SHIFT,
+        }) => Y::SelectDown,
+        X::Key(KeyEvent {
+            code: Left,
+            modifiers: KeyModifiers::SHIFT,
+        }) => Y::SelectParent,
         X::Key(KeyEvent { code: Left, .. }) => Y::NavLeft,
         X::Key(KeyEvent { code: PageDown, .. }) => Y::EditWrap,
         X::Key(KeyEvent { code: PageUp, .. }) => Y::EditUnwrap,
         X::Key(KeyEvent { code: Right, .. }) => Y::NavRight,
         X::Key(KeyEvent { code: Up, .. }) => Y::NavUp,
         X::Key(KeyEvent { code: Down, .. }) => Y::NavDown,
+        X::Key(KeyEvent { code: Home, .. }) => Y::NavHome,
+        X::Key(KeyEvent { code: End, .. }) => Y::NavEnd,
+        X::Key(KeyEvent { code: Enter, .. }) => Y::Confirm,
+        X::Key(KeyEvent { code: Esc, .. }) => Y::Cancel,
+        X::Key(KeyEvent { code: Tab, .. }) => Y::Cycle,
+        X::Key(KeyEvent { code: BackTab, .. }) => Y::CycleBack,
+        X::Key(KeyEvent { code: F(2), .. }) => Y::Rename,
+        X::Key(KeyEvent { code: F(3), .. }) => Y::MacroRecord,
+        X::Key(KeyEvent { code: F(4), .. }) => Y::MacroPlay,
+        X::Key(KeyEvent { code: F(5), .. }) => Y::Command("reformat"),
         _ => Y::Unknown,
     }
 }
@@ -1,6 +1,7 @@
 pub struct TextBuffer<S> {
     text: Vec2D<char>,
     style: Vec2D<S>,
+    cursor: Option<(usize, usize)>,
 }
 
 impl<S: Clone + Default> TextBuffer<S> {
@@ -8,6 +9,7 @@ impl<S: Clone + Default> TextBuffer<S> {
         TextBuffer {
             text: Vec2D::new(width, height),
             style: Vec2D::new(width, height),
+            cursor: None,
         }
     }
 
@@ -19,6 +21,7 @@ impl<S: Clone + Default> TextBuffer<S> {
     pub fn clear(&mut self, ch: char, style: S) {
         self.text.fill(ch);
         self.style.fill(style);
+        self.cursor = None;
     }
 
     pub fn set_char(&mut self, x: usize, y: usize, ch: char, style: S) {
@@ -26,6 +29,10 @@ impl<S: Clone + Default> TextBuffer<S> {
         self.style.set(x, y, style);
     }
 
+    pub fn set_cursor(&mut self, pos: Option<(usize, usize)>) {
+        self.cursor = pos;
+    }
+
     pub fn render<T: RenderTarget<Style = S>>(&self, target: &mut T) -> Result<(), T::Error> {
         target.prepare()?;
         for (text_row, style_row) in self.text.iter_rows().zip(self.style.iter_rows()) {
@@ -33,9 +40,16 @@ impl<S: Clone + Default> TextBuffer<S> {
                 target.write_char(ch, s)?;
             }
         }
+        target.set_cursor(self.cursor)?;
         target.finalize()
     }
 
+    pub fn draw_text(&mut self, x: usize, y: usize, text: &str, style: S) {
+        for (i, ch) in text.chars().enumerate() {
+            self.set_char(x + i, y, ch, style.clone());
+        }
+    }
+
     pub fn fill_rect(&mut self, x0: usize, y0: usize, x1: usize, y1: usize, ch: char, style: S) {
         self.text.set_rect(x0, y0, x1, y1, ch);
         self.style.set_rect(x0, y0, x1, y1, style);
@@ -60,6 +74,7 @@ pub trait RenderTarget {
     fn prepare(&mut self) -> Result<(), Self::Error>;
     fn finalize(&mut self) -> Result<(), Self::Error>;
     fn write_char(&mut self, ch: char, s: &Self::Style) -> Result<(), Self::Error>;
+    fn set_cursor(&mut self, pos: Option<(usize, usize)>) -> Result<(), Self::Error>;
 }
 
 struct Vec2D<T> {
@@ -82,10 +97,6 @@ impl<T> Vec2D<T> {
         self.shape.0
     }
 
-    pub fn get(&self, col: usize, row: usize) -> &T {
-        &self.data[self.index(col, row)]
-    }
-
     pub fn set(&mut self, col: usize, row: usize, value: T) {
         let idx = self.index(col, row);
         self.data[idx] = value